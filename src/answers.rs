@@ -0,0 +1,40 @@
+//! Selection of the answers to a question from the answer section.
+
+use alloc::vec::{Vec};
+
+use crate::{Data, Record, Type};
+use crate::canonical::{names_equal};
+
+/// Maximum number of aliases that are followed.
+pub const MAX_ALIASES: usize = 16;
+
+/// Returns the names of the alias chain starting at `name`: `name` itself followed by
+/// the targets of the CNAME records in the order in which they are reached.
+///
+/// The chain ends at a name without a CNAME record, at a loop, or after `MAX_ALIASES`
+/// aliases.
+pub fn chain<'a>(answer: &'a [Record], name: &'a str) -> Vec<&'a str> {
+    let mut res = vec!(name);
+    while res.len() <= MAX_ALIASES {
+        let cur = res[res.len() - 1];
+        let next = answer.iter().find_map(|r| match r.data {
+            Data::Cname(ref target) if names_equal(&r.name, cur) => Some(&target[..]),
+            _ => None,
+        });
+        match next {
+            Some(next) if !res.iter().any(|n| names_equal(n, next)) => res.push(next),
+            _ => break,
+        }
+    }
+    res
+}
+
+/// Returns the records of type `ty` whose owner is part of the alias chain starting at
+/// `name`. `Type::All` selects records of all types.
+pub fn records<'a>(answer: &'a [Record], name: &str, ty: Type) -> Vec<&'a Record> {
+    let chain = chain(answer, name);
+    answer.iter().filter(|r| {
+        (ty == Type::All || r.data.to_type() == ty)
+            && chain.iter().any(|n| names_equal(n, &r.name))
+    }).collect()
+}
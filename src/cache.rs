@@ -0,0 +1,452 @@
+//! A cache of DNS responses.
+//!
+//! Stores the RRsets of responses for as long as their TTLs allow and remembers names
+//! and types without records (RFC 2308). The cache can be shared between threads.
+
+use std::collections::{BTreeMap};
+use std::sync::{Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{Data, Packet, Record, ResponseCode, RRset, Type, Class};
+use crate::answers::{self, MAX_ALIASES};
+use crate::canonical;
+
+/// Configuration of a cache.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct CacheConfig {
+    /// Minimum time an entry is kept. Shorter TTLs are raised to this value. Takes
+    /// precedence over `max_ttl`.
+    pub min_ttl:          Duration,
+    /// Maximum time a positive entry is kept. Longer TTLs are lowered to this value.
+    pub max_ttl:          Duration,
+    /// Maximum time a negative entry is kept.
+    pub max_negative_ttl: Duration,
+    /// Maximum number of entries. If it is exceeded, the least recently used entries
+    /// are removed.
+    pub capacity:         usize,
+}
+
+impl Default for CacheConfig {
+    /// Returns a configuration that keeps entries for at most a day, negative entries
+    /// for at most three hours (RFC 2308 section 5), and at most 4096 entries.
+    fn default() -> CacheConfig {
+        CacheConfig {
+            min_ttl:          Duration::from_secs(0),
+            max_ttl:          Duration::from_secs(24 * 60 * 60),
+            max_negative_ttl: Duration::from_secs(3 * 60 * 60),
+            capacity:         4096,
+        }
+    }
+}
+
+/// Owner name in lower case without a trailing dot, type and class.
+///
+/// The non-existence of a name is stored with the type `Type::All`.
+type Key = (String, u16, u16);
+
+enum Value {
+    /// The records of the name and type.
+    Records(RRset),
+    /// The name or type does not exist. Contains the SOA record of the response.
+    Negative(Record),
+}
+
+struct Entry {
+    value:   Value,
+    expires: Instant,
+    used:    u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: BTreeMap<Key, Entry>,
+    /// Keys by the time they were last used.
+    lru:     BTreeMap<u64, Key>,
+    tick:    u64,
+}
+
+/// A thread-safe cache of DNS responses.
+///
+/// Each RRset is stored separately under its owner, type and class. Names are compared
+/// without regard to case.
+pub struct Cache {
+    config:  CacheConfig,
+    entries: Mutex<Entries>,
+}
+
+impl Cache {
+    /// Creates an empty cache.
+    pub fn new(config: CacheConfig) -> Cache {
+        Cache {
+            config:  config,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Stores the data of a response to a query with one question.
+    ///
+    /// The RRsets of the answer section that belong to the alias chain of the question
+    /// are stored (see `Packet::answers_for`); other records are ignored. If the
+    /// response is `NameError`, the end of the chain is remembered as non-existent. If
+    /// it is `Ok` without records of the queried type, the type is remembered as absent.
+    /// Such negative entries are kept for the TTL of the SOA record in the authority
+    /// section or its minimum field, whichever is lower, and not at all without a SOA
+    /// record (RFC 2308 section 5).
+    ///
+    /// Queries, truncated responses and responses with other response codes are
+    /// ignored.
+    pub fn insert(&self, response: &Packet) {
+        if response.is_query || response.truncated || response.question.len() != 1 {
+            return;
+        }
+        let q = &response.question[0];
+        let name_error = match response.response_code {
+            ResponseCode::Ok => false,
+            ResponseCode::NameError => true,
+            _ => return,
+        };
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let chain = answers::chain(&response.answer, &q.name);
+        for rrset in response.rrsets(crate::Section::Answer) {
+            let relevant = rrset.class == q.class
+                && (q.ty == Type::All || rrset.ty == q.ty || rrset.ty == Type::Cname)
+                && chain.iter().any(|n| canonical::names_equal(n, &rrset.name));
+            if !relevant {
+                continue;
+            }
+            let ttl = rrset.time_to_live.min(self.config.max_ttl).max(self.config.min_ttl);
+            let key = key(&rrset.name, rrset.ty, rrset.class);
+            self.put(&mut entries, key, Value::Records(rrset), now + ttl);
+        }
+        let end = chain[chain.len() - 1];
+        let ty = if name_error {
+            Type::All
+        } else if q.ty == Type::All || !response.answers_for(&q.name, q.ty).is_empty() {
+            return;
+        } else {
+            q.ty
+        };
+        let soa = response.authority.iter().find_map(|r| match r.data {
+            Data::Soa(.., minimum) => Some((r, minimum)),
+            _ => None,
+        });
+        let (soa, minimum) = match soa {
+            Some(s) => s,
+            _ => return,
+        };
+        let ttl = soa.time_to_live.min(minimum).min(self.config.max_negative_ttl)
+                     .max(self.config.min_ttl);
+        self.put(&mut entries, key(end, ty, q.class), Value::Negative(soa.clone()), now + ttl);
+    }
+
+    fn put(&self, entries: &mut Entries, key: Key, value: Value, expires: Instant) {
+        if expires <= Instant::now() {
+            return;
+        }
+        entries.tick += 1;
+        let used = entries.tick;
+        let old = entries.entries.insert(key.clone(), Entry {
+            value:   value,
+            expires: expires,
+            used:    used,
+        });
+        if let Some(old) = old {
+            entries.lru.remove(&old.used);
+        }
+        entries.lru.insert(used, key);
+        while entries.entries.len() > self.config.capacity {
+            let (_, key) = entries.lru.pop_first().unwrap();
+            entries.entries.remove(&key);
+        }
+    }
+
+    /// Returns a response to the first question of `query` from the cached data.
+    ///
+    /// CNAME records are followed through the cache. The TTLs of the records are the
+    /// time they have left in the cache. Returns `None` if the data is incomplete or
+    /// has expired. Questions of type `Type::All` are never answered.
+    pub fn lookup(&self, query: &Packet) -> Option<Packet> {
+        let q = query.question.first()?;
+        if q.ty == Type::All {
+            return None;
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let mut response = Packet::response_to(query).recursion_available(true);
+        let mut name = q.name.clone();
+        for _ in 0..=MAX_ALIASES {
+            let nxdomain = entries.get(&name, Type::All, q.class, now);
+            if let Some((Value::Negative(soa), ttl)) = nxdomain {
+                return Some(response.nxdomain().authority(with_ttl(soa, ttl)).build());
+            }
+            match entries.get(&name, q.ty, q.class, now) {
+                Some((Value::Records(rrset), ttl)) => {
+                    for r in rrset.records() {
+                        response = response.answer(with_ttl(&r, ttl));
+                    }
+                    return Some(response.build());
+                },
+                Some((Value::Negative(soa), ttl)) => {
+                    return Some(response.authority(with_ttl(soa, ttl)).build());
+                },
+                None => { },
+            }
+            if q.ty == Type::Cname {
+                return None;
+            }
+            let (rrset, ttl) = match entries.get(&name, Type::Cname, q.class, now) {
+                Some((Value::Records(rrset), ttl)) => (rrset, ttl),
+                _ => return None,
+            };
+            name = match rrset.data.first() {
+                Some(Data::Cname(target)) => target.clone(),
+                _ => return None,
+            };
+            for r in rrset.records() {
+                response = response.answer(with_ttl(&r, ttl));
+            }
+        }
+        None
+    }
+
+    /// Removes all entries of a name, regardless of their type and class.
+    pub fn flush_name(&self, name: &str) {
+        let name = key(name, Type::All, Class::All).0;
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let Entries { ref mut entries, ref mut lru, .. } = *entries;
+        entries.retain(|k, e| {
+            if k.0 == name {
+                lru.remove(&e.used);
+            }
+            k.0 != name
+        });
+    }
+
+    /// Removes all entries.
+    pub fn flush(&self) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        *entries = Entries::default();
+    }
+
+    /// Returns the number of entries, including expired entries that have not been
+    /// removed yet.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).entries.len()
+    }
+
+    /// Returns whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Entries {
+    /// Returns the value of an entry that has not expired and the time it has left.
+    /// Marks the entry as used and removes it if it has expired.
+    fn get(&mut self, name: &str, ty: Type, class: Class,
+           now: Instant) -> Option<(&Value, Duration)> {
+        let key = key(name, ty, class);
+        if self.entries.get(&key)?.expires <= now {
+            let entry = self.entries.remove(&key).unwrap();
+            self.lru.remove(&entry.used);
+            return None;
+        }
+        let entry = self.entries.get_mut(&key).unwrap();
+        self.lru.remove(&entry.used);
+        self.tick += 1;
+        entry.used = self.tick;
+        self.lru.insert(self.tick, key);
+        Some((&entry.value, entry.expires - now))
+    }
+}
+
+fn key(name: &str, ty: Type, class: Class) -> Key {
+    (name.trim_end_matches('.').to_ascii_lowercase(), ty as u16, class as u16)
+}
+
+fn with_ttl(record: &Record, ttl: Duration) -> Record {
+    Record {
+        time_to_live: ttl,
+        ..record.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr};
+    use crate::{Question};
+
+    fn query(name: &str, ty: Type) -> Packet {
+        let mut query = Packet::query(1);
+        query.question.push(Question {
+            name:  name.to_string(),
+            ty:    ty,
+            class: Class::In,
+        });
+        query
+    }
+
+    fn record(name: &str, ttl: u64, data: Data) -> Record {
+        Record {
+            name:         name.to_string(),
+            class:        Class::In,
+            time_to_live: Duration::from_secs(ttl),
+            data:         data,
+        }
+    }
+
+    fn a(name: &str, ttl: u64, ip: [u8; 4]) -> Record {
+        record(name, ttl, Data::A(Ipv4Addr::from(ip)))
+    }
+
+    fn soa(ttl: u64, minimum: u64) -> Record {
+        let secs = Duration::from_secs;
+        record("example.com", ttl, Data::Soa("ns.example.com".to_string(),
+                                             "hostmaster.example.com".to_string(), 1,
+                                             secs(3600), secs(600), secs(86400),
+                                             secs(minimum)))
+    }
+
+    fn ttls(response: &Packet) -> Vec<u64> {
+        response.answer.iter().chain(&response.authority)
+                .map(|r| r.time_to_live.as_secs()).collect()
+    }
+
+    #[test]
+    fn ttl_limits() {
+        let cache = Cache::new(CacheConfig {
+            min_ttl: Duration::from_secs(60),
+            max_ttl: Duration::from_secs(600),
+            ..CacheConfig::default()
+        });
+        let q = query("low.example.com", Type::A);
+        cache.insert(&Packet::response_to(&q).answer(a("low.example.com", 1, [1, 2, 3, 4]))
+                                              .build());
+        let q2 = query("high.example.com", Type::A);
+        cache.insert(&Packet::response_to(&q2).answer(a("high.example.com", 9999, [1, 2, 3, 4]))
+                                               .build());
+        let low = ttls(&cache.lookup(&q).unwrap())[0];
+        let high = ttls(&cache.lookup(&q2).unwrap())[0];
+        assert!(low > 55 && low <= 60);
+        assert!(high > 595 && high <= 600);
+    }
+
+    /// A minimum above the maximum takes precedence.
+    #[test]
+    fn inverted_ttl_limits() {
+        let cache = Cache::new(CacheConfig {
+            min_ttl: Duration::from_secs(600),
+            max_ttl: Duration::from_secs(60),
+            ..CacheConfig::default()
+        });
+        let q = query("example.com", Type::A);
+        cache.insert(&Packet::response_to(&q).answer(a("example.com", 300, [1, 2, 3, 4]))
+                                              .build());
+        let ttl = ttls(&cache.lookup(&q).unwrap())[0];
+        assert!(ttl > 595 && ttl <= 600);
+    }
+
+    #[test]
+    fn nxdomain() {
+        let cache = Cache::new(CacheConfig::default());
+        let q = query("missing.example.com", Type::A);
+        cache.insert(&Packet::response_to(&q).nxdomain().authority(soa(3600, 300)).build());
+        // The name does not exist for any type.
+        let response = cache.lookup(&query("MISSING.example.com.", Type::Mx)).unwrap();
+        assert_eq!(response.response_code, ResponseCode::NameError);
+        assert!(response.answer.is_empty());
+        // The TTL is the lower of the SOA TTL and its minimum field.
+        let ttl = ttls(&response)[0];
+        assert!(ttl > 295 && ttl <= 300);
+    }
+
+    #[test]
+    fn nodata() {
+        let cache = Cache::new(CacheConfig {
+            max_negative_ttl: Duration::from_secs(100),
+            ..CacheConfig::default()
+        });
+        let q = query("example.com", Type::Mx);
+        cache.insert(&Packet::response_to(&q).authority(soa(3600, 3600)).build());
+        let response = cache.lookup(&q).unwrap();
+        assert_eq!(response.response_code, ResponseCode::Ok);
+        assert!(response.answer.is_empty());
+        let ttl = ttls(&response)[0];
+        assert!(ttl > 95 && ttl <= 100);
+        // Only the type is absent.
+        assert!(cache.lookup(&query("example.com", Type::A)).is_none());
+    }
+
+    /// Negative responses without a SOA record are not cached.
+    #[test]
+    fn negative_without_soa() {
+        let cache = Cache::new(CacheConfig::default());
+        let q = query("missing.example.com", Type::A);
+        cache.insert(&Packet::response_to(&q).nxdomain().build());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn follows_cnames() {
+        let cache = Cache::new(CacheConfig::default());
+        let q = query("www.example.com", Type::A);
+        let cname = record("www.example.com", 300, Data::Cname("web.example.com".to_string()));
+        cache.insert(&Packet::response_to(&q).answer(cname.clone())
+                                              .answer(a("web.example.com", 300, [1, 2, 3, 4]))
+                                              .build());
+        assert_eq!(cache.len(), 2);
+        let response = cache.lookup(&q).unwrap();
+        assert_eq!(response.answer.len(), 2);
+        assert_eq!(response.answer[0].data, cname.data);
+        assert_eq!(response.answer[1].data, Data::A(Ipv4Addr::new(1, 2, 3, 4)));
+        // The target can be looked up on its own.
+        let response = cache.lookup(&query("web.example.com", Type::A)).unwrap();
+        assert_eq!(response.answer.len(), 1);
+        // Without the target, the chain is incomplete.
+        cache.flush_name("web.example.com");
+        assert!(cache.lookup(&q).is_none());
+    }
+
+    #[test]
+    fn lru_eviction() {
+        let cache = Cache::new(CacheConfig {
+            capacity: 2,
+            ..CacheConfig::default()
+        });
+        let names = ["a.example.com", "b.example.com", "c.example.com"];
+        let queries: Vec<_> = names.iter().map(|n| query(n, Type::A)).collect();
+        for (q, n) in queries.iter().zip(&names) {
+            if *n == "c.example.com" {
+                // Use the first entry so that the second one is the oldest.
+                assert!(cache.lookup(&queries[0]).is_some());
+            }
+            cache.insert(&Packet::response_to(q).answer(a(n, 300, [1, 2, 3, 4])).build());
+        }
+        assert_eq!(cache.len(), 2);
+        assert!(cache.lookup(&queries[0]).is_some());
+        assert!(cache.lookup(&queries[1]).is_none());
+        assert!(cache.lookup(&queries[2]).is_some());
+    }
+
+    #[test]
+    fn flush_name() {
+        let cache = Cache::new(CacheConfig::default());
+        let a_query = query("example.com", Type::A);
+        let mx_query = query("example.com", Type::Mx);
+        let other = query("example.org", Type::A);
+        let mx = record("example.com", 300, Data::Mx(10, "mx.example.com".to_string()));
+        cache.insert(&Packet::response_to(&a_query).answer(a("example.com", 300, [1, 2, 3, 4]))
+                                                    .build());
+        cache.insert(&Packet::response_to(&mx_query).answer(mx).build());
+        cache.insert(&Packet::response_to(&other).answer(a("example.org", 300, [1, 2, 3, 4]))
+                                                  .build());
+        assert_eq!(cache.len(), 3);
+        cache.flush_name("EXAMPLE.com.");
+        assert_eq!(cache.len(), 1);
+        assert!(cache.lookup(&a_query).is_none());
+        assert!(cache.lookup(&mx_query).is_none());
+        assert!(cache.lookup(&other).is_some());
+    }
+}
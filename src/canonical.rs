@@ -0,0 +1,134 @@
+//! Canonical form and ordering of records (RFC 4034 section 6).
+
+use core::cmp::{Ordering};
+
+use alloc::vec::{Vec};
+use alloc::string::{String};
+
+use crate::{Data, Record, format, len};
+
+/// Returns the labels of a domain name. A trailing dot is ignored.
+fn labels(name: &str) -> Vec<&str> {
+    let name = name.trim_end_matches('.');
+    if name.is_empty() {
+        vec!()
+    } else {
+        name.split('.').collect()
+    }
+}
+
+/// Compares two domain names in canonical order.
+pub fn compare_names(a: &str, b: &str) -> Ordering {
+    let a = labels(a);
+    let b = labels(b);
+    for (a, b) in a.iter().rev().zip(b.iter().rev()) {
+        let a = a.bytes().map(|c| c.to_ascii_lowercase());
+        let b = b.bytes().map(|c| c.to_ascii_lowercase());
+        match a.cmp(b) {
+            Ordering::Equal => { },
+            o => return o,
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+/// Returns whether `name` is equal to or below `zone`.
+pub fn is_subdomain(name: &str, zone: &str) -> bool {
+    let name = labels(name);
+    let zone = labels(zone);
+    name.len() >= zone.len() && name.iter().rev().zip(zone.iter().rev()).all(|(a, b)| {
+        a.eq_ignore_ascii_case(b)
+    })
+}
+
+/// Returns whether two names are equal, ignoring case and a trailing dot.
+pub fn names_equal(a: &str, b: &str) -> bool {
+    compare_names(a, b) == Ordering::Equal
+}
+
+/// Returns the uncompressed wire form of a name with all letters in lower case.
+pub fn name(s: &str) -> Vec<u8> {
+    let mut res = vec!();
+    for label in labels(s) {
+        res.push(label.len() as u8);
+        res.extend(label.bytes().map(|c| c.to_ascii_lowercase()));
+    }
+    res.push(0);
+    res
+}
+
+fn lower(s: &str) -> String {
+    s.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Returns the canonical wire form of record data.
+pub fn data(d: &Data) -> Vec<u8> {
+    let d = match *d {
+        Data::Mx(preference, ref domain) => Data::Mx(preference, lower(domain)),
+        Data::Ns(ref domain) => Data::Ns(lower(domain)),
+        Data::Cname(ref domain) => Data::Cname(lower(domain)),
+        Data::Ptr(ref domain) => Data::Ptr(lower(domain)),
+        Data::Rp(ref mbox, ref txt) => Data::Rp(lower(mbox), lower(txt)),
+        Data::Soa(ref mname, ref rname, serial, refresh, retry, expire, minimum) =>
+            Data::Soa(lower(mname), lower(rname), serial, refresh, retry, expire, minimum),
+        _ => d.clone(),
+    };
+    let mut res = vec!(0; len::data(&d));
+    {
+        let mut dst = &mut res[..];
+        let _ = format::data(&mut dst, &d);
+    }
+    res
+}
+
+/// Returns the canonical wire form of a record.
+pub fn record(r: &Record) -> Vec<u8> {
+    let data = data(&r.data);
+    let mut res = name(&r.name);
+    let ty = r.data.to_type() as u16;
+    let class = r.class as u16;
+    let ttl = r.time_to_live.as_secs() as u32;
+    let len = data.len() as u16;
+    res.extend([(ty >> 8) as u8, ty as u8, (class >> 8) as u8, class as u8].iter().cloned());
+    res.extend([(ttl >> 24) as u8, (ttl >> 16) as u8, (ttl >> 8) as u8, ttl as u8].iter()
+                                                                                 .cloned());
+    res.extend([(len >> 8) as u8, len as u8].iter().cloned());
+    res.extend(data);
+    res
+}
+
+/// Returns the records sorted in canonical order with duplicates removed.
+///
+/// Records are ordered by owner name, type, class and record data.
+pub fn sort(records: &[Record]) -> Vec<&Record> {
+    let mut keyed: Vec<_> = records.iter().map(|r| (r, data(&r.data))).collect();
+    keyed.sort_by(|a, b| {
+        match compare_names(&a.0.name, &b.0.name) {
+            Ordering::Equal => { },
+            o => return o,
+        }
+        match (a.0.data.to_type() as u16).cmp(&(b.0.data.to_type() as u16)) {
+            Ordering::Equal => { },
+            o => return o,
+        }
+        match (a.0.class as u16).cmp(&(b.0.class as u16)) {
+            Ordering::Equal => { },
+            o => return o,
+        }
+        a.1.cmp(&b.1)
+    });
+    let mut res: Vec<(&Record, Vec<u8>)> = vec!();
+    for (r, d) in keyed {
+        let duplicate = match res.last() {
+            Some(&(l, ref ld)) => {
+                names_equal(&l.name, &r.name) && l.data.to_type() == r.data.to_type()
+                    && l.class == r.class && *ld == d
+            },
+            _ => false,
+        };
+        if !duplicate {
+            res.push((r, d));
+        }
+    }
+    res.into_iter().map(|r| r.0).collect()
+}
@@ -0,0 +1,315 @@
+//! Automated maintenance of delegations.
+//!
+//! Computes the changes that a parent has to apply to the DS, NS and glue records of a
+//! delegation based on the CDS, CDNSKEY and CSYNC records published by the child.
+//!
+//! See RFC 7344, RFC 7477 and RFC 8078 for more details.
+
+use alloc::vec::{Vec};
+use alloc::string::{ToString};
+
+use crate::{Class, Data, Record, Type};
+use crate::canonical;
+use crate::sha2::{Sha256, Sha512};
+
+/// CSYNC flag: the parent may apply the changes without further approval.
+const IMMEDIATE: u16 = 1;
+/// CSYNC flag: the child's SOA serial must be at least the CSYNC serial.
+const SOA_MINIMUM: u16 = 2;
+
+/// DNSKEY flag: the key is a zone key.
+const ZONE_KEY: u16 = 0x0100;
+
+/// A DS digest type.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DigestType {
+    /// SHA-256.
+    Sha256 = 2,
+    /// SHA-384.
+    Sha384 = 4,
+}
+
+impl DigestType {
+    fn from_u8(ty: u8) -> Option<DigestType> {
+        match ty {
+            2 => Some(DigestType::Sha256),
+            4 => Some(DigestType::Sha384),
+            _ => None,
+        }
+    }
+}
+
+/// The records published by the child zone.
+#[derive(Clone, Debug)]
+pub struct Child<'a> {
+    /// Name of the child zone.
+    pub zone:       &'a str,
+    /// The CDS RRset.
+    pub cds:        &'a [Record],
+    /// The CDNSKEY RRset.
+    pub cdnskey:    &'a [Record],
+    /// The CSYNC record.
+    pub csync:      Option<&'a Record>,
+    /// Serial of the child's SOA record.
+    pub soa_serial: Option<u32>,
+    /// The child's NS RRset.
+    pub ns:         &'a [Record],
+    /// The child's A and AAAA records of nameservers below the child zone.
+    pub addresses:  &'a [Record],
+}
+
+/// The records of the delegation currently published by the parent zone.
+#[derive(Clone, Debug)]
+pub struct Parent<'a> {
+    /// The DS RRset.
+    pub ds:   &'a [Record],
+    /// The NS RRset.
+    pub ns:   &'a [Record],
+    /// The glue records.
+    pub glue: &'a [Record],
+}
+
+/// Changes to the parent zone.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Changes {
+    /// Records to add.
+    pub add:       Vec<Record>,
+    /// Records to remove.
+    pub remove:    Vec<Record>,
+    /// Set if the NS and glue changes may be applied without out-of-band approval, i.e.,
+    /// if the CSYNC record has the immediate flag set.
+    pub immediate: bool,
+}
+
+/// An error that prevents processing of the child's records.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The CDS and CDNSKEY RRsets describe different keys.
+    Inconsistent,
+    /// A delete request is mixed with other records.
+    InvalidDelete,
+    /// A CDNSKEY record is not a zone key.
+    InvalidKey,
+    /// The CSYNC record lists a type that cannot be synchronized.
+    UnsupportedType,
+    /// The child's SOA serial is lower than the CSYNC serial.
+    SoaMinimum,
+}
+
+/// Computes the changes to the parent zone.
+///
+/// DS records derived from CDNSKEY records use `digest`. If both CDS and CDNSKEY
+/// records are present, they must describe the same keys and the CDS records are used.
+pub fn process(child: &Child, parent: &Parent,
+               digest: DigestType) -> Result<Changes, Error> {
+    let mut changes = Changes::default();
+    if let Some(ds) = desired_ds(child, digest)? {
+        diff(&mut changes, parent.ds, &ds);
+    }
+    if let Some(csync) = child.csync {
+        process_csync(&mut changes, child, parent, csync)?;
+    }
+    Ok(changes)
+}
+
+/// Returns the DS RRset requested by the child or `None` if no change is requested.
+fn desired_ds(child: &Child, digest: DigestType) -> Result<Option<Vec<Record>>, Error> {
+    let cds_delete = is_delete(child.cds, |d| match *d {
+        Data::Cds(0, 0, 0, ref digest) => *digest == [0],
+        _ => false,
+    })?;
+    let cdnskey_delete = is_delete(child.cdnskey, |d| match *d {
+        Data::Cdnskey(0, 3, 0, ref key) => *key == [0],
+        _ => false,
+    })?;
+    let cds = child.cds.iter().filter(|r| r.data.to_type() == Type::Cds).count();
+    let cdnskey = child.cdnskey.iter().filter(|r| r.data.to_type() == Type::Cdnskey).count();
+    match (cds, cdnskey) {
+        (0, 0) => return Ok(None),
+        (0, _) if cdnskey_delete => return Ok(Some(vec!())),
+        (_, 0) if cds_delete => return Ok(Some(vec!())),
+        (_, _) if cds_delete && cdnskey_delete => return Ok(Some(vec!())),
+        (_, _) if cds_delete || cdnskey_delete => return Err(Error::Inconsistent),
+        _ => { },
+    }
+
+    let mut from_cds = vec!();
+    for r in child.cds {
+        if let Data::Cds(tag, alg, ty, ref d) = r.data {
+            from_cds.push(ds_record(child.zone, r, Data::Ds(tag, alg, ty, d.clone())));
+        }
+    }
+    let mut from_cdnskey = vec!();
+    for r in child.cdnskey {
+        if let Data::Cdnskey(flags, ..) = r.data {
+            if flags & ZONE_KEY == 0 {
+                return Err(Error::InvalidKey);
+            }
+            // Compute the digests with the types used by the CDS records, if any, so
+            // that both sets can be compared.
+            let mut types = vec!();
+            for d in &from_cds {
+                if let Data::Ds(_, _, ty, _) = d.data {
+                    match DigestType::from_u8(ty) {
+                        Some(ty) if !types.contains(&ty) => types.push(ty),
+                        _ => { },
+                    }
+                }
+            }
+            if types.is_empty() {
+                types.push(digest);
+            }
+            for ty in types {
+                let ds = ds_from_key(child.zone, &r.data, ty)?;
+                from_cdnskey.push(ds_record(child.zone, r, ds));
+            }
+        }
+    }
+
+    if from_cds.is_empty() {
+        return Ok(Some(from_cdnskey));
+    }
+    if !from_cdnskey.is_empty() {
+        // Every key must have a CDS record and every CDS record with a supported digest
+        // type must have a key.
+        let keys_covered = from_cdnskey.iter().all(|k| from_cds.iter().any(|c| same(c, k)));
+        let cds_covered = from_cds.iter().all(|c| match c.data {
+            Data::Ds(_, _, ty, _) if DigestType::from_u8(ty).is_some() => {
+                from_cdnskey.iter().any(|k| same(c, k))
+            },
+            _ => true,
+        });
+        if !keys_covered || !cds_covered {
+            return Err(Error::Inconsistent);
+        }
+    }
+    Ok(Some(from_cds))
+}
+
+/// Returns whether the RRset is a delete request. Fails if the delete record is mixed
+/// with other records.
+fn is_delete<F: Fn(&Data) -> bool>(rrset: &[Record], delete: F) -> Result<bool, Error> {
+    let n = rrset.iter().filter(|r| delete(&r.data)).count();
+    match n {
+        0 => Ok(false),
+        _ if n == rrset.len() => Ok(true),
+        _ => Err(Error::InvalidDelete),
+    }
+}
+
+fn ds_record(zone: &str, template: &Record, data: Data) -> Record {
+    Record {
+        name:         zone.to_string(),
+        class:        template.class,
+        time_to_live: template.time_to_live,
+        data:         data,
+    }
+}
+
+/// Computes the DS data of a DNSKEY or CDNSKEY.
+fn ds_from_key(zone: &str, key: &Data, ty: DigestType) -> Result<Data, Error> {
+    let (flags, protocol, alg, key) = match *key {
+        Data::Dnskey(f, p, a, ref k) | Data::Cdnskey(f, p, a, ref k) => (f, p, a, k),
+        _ => return Err(Error::InvalidKey),
+    };
+    let mut rdata = vec!((flags >> 8) as u8, flags as u8, protocol, alg);
+    rdata.extend(key.iter().cloned());
+    let owner = canonical::name(zone);
+    let digest = match ty {
+        DigestType::Sha256 => {
+            let mut ctx = Sha256::new();
+            ctx.update(&owner);
+            ctx.update(&rdata);
+            ctx.finish()
+        },
+        DigestType::Sha384 => {
+            let mut ctx = Sha512::new384();
+            ctx.update(&owner);
+            ctx.update(&rdata);
+            ctx.finish()
+        },
+    };
+    Ok(Data::Ds(key_tag(&rdata), alg, ty as u8, digest))
+}
+
+/// Computes the key tag of DNSKEY data (RFC 4034 appendix B).
+fn key_tag(rdata: &[u8]) -> u16 {
+    let mut acc = 0u32;
+    for (i, &b) in rdata.iter().enumerate() {
+        acc += if i & 1 == 0 { (b as u32) << 8 } else { b as u32 };
+    }
+    acc += (acc >> 16) & 0xffff;
+    acc as u16
+}
+
+fn process_csync(changes: &mut Changes, child: &Child, parent: &Parent,
+                 csync: &Record) -> Result<(), Error> {
+    let (serial, flags, types) = match csync.data {
+        Data::Csync(serial, flags, ref types) => (serial, flags, types),
+        _ => return Ok(()),
+    };
+    if flags & SOA_MINIMUM != 0 {
+        match child.soa_serial {
+            Some(s) if !serial_lt(s, serial) => { },
+            _ => return Err(Error::SoaMinimum),
+        }
+    }
+    if types.iter().any(|&t| t != Type::Ns && t != Type::A && t != Type::Aaaa) {
+        return Err(Error::UnsupportedType);
+    }
+    changes.immediate = flags & IMMEDIATE != 0;
+
+    let ns = if types.contains(&Type::Ns) {
+        let ns: Vec<_> = child.ns.iter().filter(|r| r.data.to_type() == Type::Ns)
+                                        .cloned().collect();
+        diff(changes, parent.ns, &ns);
+        ns
+    } else {
+        parent.ns.to_vec()
+    };
+
+    // Glue is only needed for nameservers below the child zone.
+    let glue_names: Vec<&str> = ns.iter().filter_map(|r| match r.data {
+        Data::Ns(ref name) if canonical::is_subdomain(name, child.zone) => Some(&name[..]),
+        _ => None,
+    }).collect();
+    for &ty in &[Type::A, Type::Aaaa] {
+        if !types.contains(&ty) {
+            continue;
+        }
+        let current: Vec<_> = parent.glue.iter().filter(|r| r.data.to_type() == ty)
+                                                .cloned().collect();
+        let desired: Vec<_> = child.addresses.iter().filter(|r| {
+            r.data.to_type() == ty && r.class == Class::In
+                && glue_names.iter().any(|n| canonical::names_equal(n, &r.name))
+        }).cloned().collect();
+        diff(changes, &current, &desired);
+    }
+    Ok(())
+}
+
+/// Returns whether serial `a` is lower than `b` in serial number arithmetic (RFC 1982).
+fn serial_lt(a: u32, b: u32) -> bool {
+    a != b && b.wrapping_sub(a) < 0x8000_0000
+}
+
+/// Returns whether two records have the same owner, type, class and data.
+fn same(a: &Record, b: &Record) -> bool {
+    canonical::names_equal(&a.name, &b.name) && a.class == b.class
+        && a.data.to_type() == b.data.to_type()
+        && canonical::data(&a.data) == canonical::data(&b.data)
+}
+
+/// Records the changes needed to turn `current` into `desired`.
+fn diff(changes: &mut Changes, current: &[Record], desired: &[Record]) {
+    for r in current {
+        if !desired.iter().any(|d| same(r, d)) {
+            changes.remove.push(r.clone());
+        }
+    }
+    for r in desired {
+        if !current.iter().any(|c| same(r, c)) {
+            changes.add.push(r.clone());
+        }
+    }
+}
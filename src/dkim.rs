@@ -0,0 +1,106 @@
+//! DKIM public key records.
+//!
+//! See RFC 6376 section 3.6.1 for more details.
+
+use alloc::vec::{Vec};
+use alloc::string::{String, ToString};
+
+use crate::tags;
+
+/// A parsed DKIM key record.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Dkim {
+    /// Acceptable hash algorithms (`h`). Empty if all algorithms are allowed.
+    pub hash_algorithms: Vec<String>,
+    /// Key type (`k`).
+    pub key_type:        String,
+    /// Notes for humans (`n`).
+    pub notes:           Option<String>,
+    /// Decoded public key data (`p`). Empty if the key has been revoked.
+    pub public_key:      Vec<u8>,
+    /// Service types to which the record applies (`s`).
+    pub service_types:   Vec<String>,
+    /// Flags (`t`).
+    pub flags:           Vec<String>,
+    /// All tags including those not known to this library.
+    pub tags:            Vec<(String, String)>,
+}
+
+impl Dkim {
+    /// Parses the concatenated text of a TXT record as a DKIM key record.
+    pub fn parse(src: &[u8]) -> Result<Dkim, ()> {
+        let tags = tags::parse(src)?;
+        let mut dkim = Dkim {
+            hash_algorithms: vec!(),
+            key_type:        "rsa".to_string(),
+            notes:           None,
+            public_key:      vec!(),
+            service_types:   vec!("*".to_string()),
+            flags:           vec!(),
+            tags:            vec!(),
+        };
+        let mut public_key = false;
+        for (i, (tag, value)) in tags.iter().enumerate() {
+            match &tag[..] {
+                "v" if i != 0 || value != "DKIM1" => return Err(()),
+                "h" => dkim.hash_algorithms = list(value),
+                "k" => dkim.key_type = value.to_string(),
+                "n" => dkim.notes = Some(value.to_string()),
+                "p" => {
+                    dkim.public_key = base64(&tags::strip_whitespace(value))?;
+                    public_key = true;
+                },
+                "s" => dkim.service_types = list(value),
+                "t" => dkim.flags = list(value),
+                _ => { },
+            }
+        }
+        if !public_key {
+            return Err(());
+        }
+        dkim.tags = tags;
+        Ok(dkim)
+    }
+
+    /// Returns whether the key has been revoked.
+    pub fn is_revoked(&self) -> bool {
+        self.public_key.is_empty()
+    }
+
+    /// Returns whether the domain is only testing DKIM (`t=y`).
+    pub fn is_testing(&self) -> bool {
+        self.flags.iter().any(|f| f == "y")
+    }
+}
+
+fn list(value: &str) -> Vec<String> {
+    value.split(':').map(|v| v.trim()).filter(|v| !v.is_empty()).map(|v| v.to_string())
+        .collect()
+}
+
+fn base64(s: &str) -> Result<Vec<u8>, ()> {
+    let s = s.trim_end_matches('=').as_bytes();
+    let mut res = Vec::with_capacity(s.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for &c in s {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(()),
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 6 {
+        return Err(());
+    }
+    Ok(res)
+}
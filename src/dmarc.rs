@@ -0,0 +1,133 @@
+//! DMARC policy records.
+//!
+//! See RFC 7489 for more details.
+
+use alloc::vec::{Vec};
+use alloc::string::{String, ToString};
+
+use crate::tags;
+
+/// A parsed DMARC record.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Dmarc {
+    /// Requested policy for the domain (`p`).
+    pub policy:            Policy,
+    /// Requested policy for subdomains (`sp`). Defaults to `policy`.
+    pub subdomain_policy:  Policy,
+    /// DKIM identifier alignment mode (`adkim`).
+    pub dkim_alignment:    Alignment,
+    /// SPF identifier alignment mode (`aspf`).
+    pub spf_alignment:     Alignment,
+    /// Percentage of messages to which the policy is applied (`pct`).
+    pub percent:           u8,
+    /// Addresses for aggregate reports (`rua`).
+    pub aggregate_reports: Vec<String>,
+    /// Addresses for failure reports (`ruf`).
+    pub failure_reports:   Vec<String>,
+    /// Failure reporting options (`fo`).
+    pub failure_options:   Vec<String>,
+    /// Failure report formats (`rf`).
+    pub report_formats:    Vec<String>,
+    /// Interval between aggregate reports in seconds (`ri`).
+    pub report_interval:   u32,
+    /// All tags including those not known to this library.
+    pub tags:              Vec<(String, String)>,
+}
+
+/// A requested mail receiver policy.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Policy {
+    /// No specific action.
+    None,
+    /// Treat failing mail as suspicious.
+    Quarantine,
+    /// Reject failing mail.
+    Reject,
+}
+
+/// An identifier alignment mode.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Alignment {
+    /// Organizational domains must match.
+    Relaxed,
+    /// Domains must match exactly.
+    Strict,
+}
+
+impl Dmarc {
+    /// Parses the concatenated text of a TXT record as a DMARC record.
+    ///
+    /// Unknown tags are ignored. Invalid values of known tags are an error.
+    pub fn parse(src: &[u8]) -> Result<Dmarc, ()> {
+        let tags = tags::parse(src)?;
+        match tags.first() {
+            Some((tag, value)) if tag == "v" && value == "DMARC1" => { },
+            _ => return Err(()),
+        }
+        let mut dmarc = Dmarc {
+            policy:            Policy::None,
+            subdomain_policy:  Policy::None,
+            dkim_alignment:    Alignment::Relaxed,
+            spf_alignment:     Alignment::Relaxed,
+            percent:           100,
+            aggregate_reports: vec!(),
+            failure_reports:   vec!(),
+            failure_options:   vec!("0".to_string()),
+            report_formats:    vec!("afrf".to_string()),
+            report_interval:   86400,
+            tags:              vec!(),
+        };
+        let mut policy = None;
+        let mut subdomain_policy = None;
+        for (tag, value) in &tags[1..] {
+            match &tag[..] {
+                "p" => policy = Some(parse_policy(value)?),
+                "sp" => subdomain_policy = Some(parse_policy(value)?),
+                "adkim" => dmarc.dkim_alignment = parse_alignment(value)?,
+                "aspf" => dmarc.spf_alignment = parse_alignment(value)?,
+                "pct" => match value.parse() {
+                    Ok(n) if n <= 100 => dmarc.percent = n,
+                    _ => return Err(()),
+                },
+                "rua" => dmarc.aggregate_reports = list(value, ','),
+                "ruf" => dmarc.failure_reports = list(value, ','),
+                "fo" => dmarc.failure_options = list(value, ':'),
+                "rf" => dmarc.report_formats = list(value, ':'),
+                "ri" => dmarc.report_interval = trycvt!(value.parse()),
+                _ => { },
+            }
+        }
+        // A record without a policy but with aggregate report addresses is treated as
+        // `p=none` (RFC 7489 section 6.6.3).
+        dmarc.policy = match policy {
+            Some(p) => p,
+            None if !dmarc.aggregate_reports.is_empty() => Policy::None,
+            None => return Err(()),
+        };
+        dmarc.subdomain_policy = subdomain_policy.unwrap_or(dmarc.policy);
+        dmarc.tags = tags;
+        Ok(dmarc)
+    }
+}
+
+fn parse_policy(value: &str) -> Result<Policy, ()> {
+    match &value.to_ascii_lowercase()[..] {
+        "none" => Ok(Policy::None),
+        "quarantine" => Ok(Policy::Quarantine),
+        "reject" => Ok(Policy::Reject),
+        _ => Err(()),
+    }
+}
+
+fn parse_alignment(value: &str) -> Result<Alignment, ()> {
+    match &value.to_ascii_lowercase()[..] {
+        "r" => Ok(Alignment::Relaxed),
+        "s" => Ok(Alignment::Strict),
+        _ => Err(()),
+    }
+}
+
+fn list(value: &str, sep: char) -> Vec<String> {
+    value.split(sep).map(|v| v.trim()).filter(|v| !v.is_empty()).map(|v| v.to_string())
+        .collect()
+}
@@ -1,10 +1,24 @@
-use std::net::{Ipv4Addr, Ipv6Addr};
-use std::io::{Write};
+use core::net::{Ipv4Addr, Ipv6Addr};
+use core::time::{Duration};
 
-use {FormatError, Data, len, Packet, Record, Question};
-use rust::{WriteExt2};
+use alloc::vec::{Vec};
+
+use crate::{FormatError, Data, len, Packet, Record, Question, Type, Edns, OPT};
+use crate::rust::{WriteExt2};
 
 pub fn packet(dst: &mut &mut [u8], p: &Packet) -> Result<(), FormatError> {
+    header(dst, p);
+    for q in &p.question { question(dst, q)?; }
+    for r in &p.answer { record(dst, r)?; }
+    for r in &p.authority { record(dst, r)?; }
+    for r in &p.additional { record(dst, r)?; }
+    if let Some(ref e) = p.edns { opt(dst, e)?; }
+    
+    Ok(())
+}
+
+/// Writes the header of the packet. The section counts are taken from the packet.
+pub fn header(dst: &mut &mut [u8], p: &Packet) {
     let _ = dst.write_i16_be(p.id).ok();
     let mut flags1 = 0;
     flags1 |= (!p.is_query as u8) << 7;
@@ -15,45 +29,87 @@ pub fn packet(dst: &mut &mut [u8], p: &Packet) -> Result<(), FormatError> {
     let _ = dst.write_u8(flags1).ok();
     let mut flags2 = 0;
     flags2 |= (p.recursion_available as u8) << 7;
-    flags2 |= p.response_code as u8;
+    flags2 |= (p.authentic_data as u8) << 5;
+    flags2 |= p.response_code as u8 & 0b1111;
     let _ = dst.write_u8(flags2).ok();
     let _ = dst.write_u16_be(p.question.len() as u16).ok();
     let _ = dst.write_u16_be(p.answer.len() as u16).ok();
     let _ = dst.write_u16_be(p.authority.len() as u16).ok();
-    let _ = dst.write_u16_be(p.additional.len() as u16).ok();
+    let num_additional = p.additional.len() + p.edns.is_some() as usize;
+    let _ = dst.write_u16_be(num_additional as u16).ok();
+}
 
-    for q in &p.question { try!(question(dst, q)); }
-    for r in &p.answer { try!(record(dst, r)); }
-    for r in &p.authority { try!(record(dst, r)); }
-    for r in &p.additional { try!(record(dst, r)); }
-    
+pub fn opt(dst: &mut &mut [u8], e: &Edns) -> Result<(), FormatError> {
+    let _ = dst.write_u8(0);
+    let _ = dst.write_u16_be(OPT);
+    let _ = dst.write_u16_be(e.udp_payload_size);
+    let _ = dst.write_u8(e.extended_rcode);
+    let _ = dst.write_u8(e.version);
+    let _ = dst.write_u16_be((e.dnssec_ok as u16) << 15);
+    let _ = dst.write_u16_be((len::opt(e) - 11) as u16);
+    for &(code, ref data) in &e.options {
+        let _ = dst.write_u16_be(code);
+        let _ = dst.write_u16_be(data.len() as u16);
+        let _ = dst.write_all(data);
+    }
     Ok(())
 }
 
-fn question(dst: &mut &mut [u8], q: &Question) -> Result<(), FormatError> {
-    try!(domain_name(dst, &q.name));
-    let _ = dst.write_u16_be(q.ty as u16);
-    let _ = dst.write_u16_be(q.class as u16);
+pub fn question(dst: &mut &mut [u8], q: &Question) -> Result<(), FormatError> {
+    question_raw(dst, &q.name, q.ty as u16, q.class as u16)
+}
+
+pub fn question_raw(dst: &mut &mut [u8], name: &str, ty: u16,
+                    class: u16) -> Result<(), FormatError> {
+    domain_name(dst, name)?;
+    let _ = dst.write_u16_be(ty);
+    let _ = dst.write_u16_be(class);
     Ok(())
 }
 
-fn record(dst: &mut &mut [u8], r: &Record) -> Result<(), FormatError> {
-    try!(domain_name(dst, &r.name));
-    let _ = dst.write_u16_be(r.data.to_type() as u16);
-    let _ = dst.write_u16_be(r.class as u16);
-    let _ = dst.write_i32_be(r.time_to_live.num_seconds() as i32);
-    let _ = dst.write_u16_be(len::data(&r.data) as u16);
+pub fn record(dst: &mut &mut [u8], r: &Record) -> Result<(), FormatError> {
+    record_header(dst, &r.name, r.data.to_type() as u16, r.class as u16,
+                  r.time_to_live.as_secs() as u32, len::data(&r.data))?;
     data(dst, &r.data)
 }
 
-fn data(dst: &mut &mut [u8], d: &Data) -> Result<(), FormatError> {
+/// Writes a record whose data is already in wire format.
+pub fn record_raw(dst: &mut &mut [u8], name: &str, ty: u16, class: u16, ttl: u32,
+                  rdata: &[u8]) -> Result<(), FormatError> {
+    record_header(dst, name, ty, class, ttl, rdata.len())?;
+    let _ = dst.write_all(rdata);
+    Ok(())
+}
+
+fn record_header(dst: &mut &mut [u8], name: &str, ty: u16, class: u16, ttl: u32,
+                 rdlen: usize) -> Result<(), FormatError> {
+    domain_name(dst, name)?;
+    let _ = dst.write_u16_be(ty);
+    let _ = dst.write_u16_be(class);
+    let _ = dst.write_u32_be(ttl);
+    let _ = dst.write_u16_be(rdlen as u16);
+    Ok(())
+}
+
+pub fn data(dst: &mut &mut [u8], d: &Data) -> Result<(), FormatError> {
     match *d {
         Data::A(ip)                      => a(dst, &ip),
         Data::Aaaa(ip)                   => aaaa(dst, &ip),
         Data::Mx(preference, ref domain) => mx(dst, preference, domain),
+        Data::Ns(ref domain)             => ns(dst, domain),
+        Data::Cname(ref domain)          => cname(dst, domain),
         Data::Ptr(ref domain)            => ptr(dst, domain),
         Data::Rp(ref mbox, ref txt)      => rp(dst, mbox, txt),
+        Data::Soa(ref mname, ref rname, serial, refresh, retry, expire, minimum) =>
+            soa(dst, mname, rname, serial, &[refresh, retry, expire, minimum]),
         Data::Txt(ref text)              => txt(dst, text),
+        Data::Ds(a, b, c, ref rest)      => key_or_digest(dst, a, b, c, rest),
+        Data::Dnskey(a, b, c, ref rest)  => key_or_digest(dst, a, b, c, rest),
+        Data::Cds(a, b, c, ref rest)     => key_or_digest(dst, a, b, c, rest),
+        Data::Cdnskey(a, b, c, ref rest) => key_or_digest(dst, a, b, c, rest),
+        Data::Csync(serial, flags, ref types) => csync(dst, serial, flags, types),
+        Data::Zonemd(serial, scheme, hash, ref digest) =>
+            zonemd(dst, serial, scheme, hash, digest),
     }
 }
 
@@ -78,39 +134,89 @@ fn mx(dst: &mut &mut [u8], preference: i16, domain: &str) -> Result<(), FormatEr
     domain_name(dst, domain)
 }
 
+fn ns(dst: &mut &mut [u8], domain: &str) -> Result<(), FormatError> {
+    domain_name(dst, domain)
+}
+
+fn cname(dst: &mut &mut [u8], domain: &str) -> Result<(), FormatError> {
+    domain_name(dst, domain)
+}
+
 fn ptr(dst: &mut &mut [u8], domain: &str) -> Result<(), FormatError> {
     domain_name(dst, domain)
 }
 
 fn rp(dst: &mut &mut [u8], mbox: &str, txt: &str) -> Result<(), FormatError> {
-    try!(domain_name(dst, mbox));
+    domain_name(dst, mbox)?;
     domain_name(dst, txt)
 }
 
-fn txt(dst: &mut &mut [u8], s: &[String]) -> Result<(), FormatError> {
+fn soa(dst: &mut &mut [u8], mname: &str, rname: &str, serial: u32,
+       timers: &[Duration]) -> Result<(), FormatError> {
+    domain_name(dst, mname)?;
+    domain_name(dst, rname)?;
+    let _ = dst.write_u32_be(serial);
+    for t in timers {
+        let _ = dst.write_u32_be(t.as_secs() as u32);
+    }
+    Ok(())
+}
+
+fn key_or_digest(dst: &mut &mut [u8], a: u16, b: u8, c: u8,
+                 rest: &[u8]) -> Result<(), FormatError> {
+    let _ = dst.write_u16_be(a);
+    let _ = dst.write_u8(b);
+    let _ = dst.write_u8(c);
+    let _ = dst.write_all(rest);
+    Ok(())
+}
+
+fn csync(dst: &mut &mut [u8], serial: u32, flags: u16,
+         types: &[Type]) -> Result<(), FormatError> {
+    let _ = dst.write_u32_be(serial);
+    let _ = dst.write_u16_be(flags);
+    let _ = dst.write_all(&len::type_bitmap(types));
+    Ok(())
+}
+
+fn zonemd(dst: &mut &mut [u8], serial: u32, scheme: u8, hash: u8,
+          digest: &[u8]) -> Result<(), FormatError> {
+    let _ = dst.write_u32_be(serial);
+    let _ = dst.write_u8(scheme);
+    let _ = dst.write_u8(hash);
+    let _ = dst.write_all(digest);
+    Ok(())
+}
+
+fn txt(dst: &mut &mut [u8], s: &[Vec<u8>]) -> Result<(), FormatError> {
     for s in s {
-        try!(character_string(dst, s));
+        character_string(dst, s)?;
     }
     Ok(())
 }
 
+/// Writes an uncompressed name. A trailing dot is ignored and the root (`""` or `"."`)
+/// is a single zero byte.
 fn domain_name(dst: &mut &mut [u8], s: &str) -> Result<(), FormatError> {
-    for part in s.split('.') {
-        if part.len() > 63 {
-            return Err(FormatError::Label(part.len()));
+    let s = s.strip_suffix('.').unwrap_or(s);
+    if !s.is_empty() {
+        for part in s.split('.') {
+            if part.len() > 63 {
+                return Err(FormatError::Label(part.len()));
+            }
+            let _ = dst.write_u8(part.len() as u8);
+            let _ = dst.write_all(part.as_bytes());
         }
-        let _ = dst.write_u8(part.len() as u8);
-        let _ = dst.write_all(part.as_bytes());
     }
     let _ = dst.write_u8(0);
     Ok(())
 }
 
-fn character_string(dst: &mut &mut [u8], s: &str) -> Result<(), FormatError> {
+fn character_string(dst: &mut &mut [u8], s: &[u8]) -> Result<(), FormatError> {
     if s.len() > 255 {
         return Err(FormatError::String(s.len()));
     }
     let _ = dst.write_u8(s.len() as u8);
-    let _ = dst.write(s.as_bytes());
+    let _ = dst.write_all(s);
     Ok(())
 }
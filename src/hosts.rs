@@ -0,0 +1,272 @@
+//! Static host names from `/etc/hosts` and the lookup order from `/etc/nsswitch.conf`.
+//!
+//! See hosts(5) and nsswitch.conf(5) for more details.
+
+use std::net::{IpAddr};
+use std::time::{SystemTime};
+use std::fs;
+
+/// A line of a hosts file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Entry {
+    /// Address of the host.
+    pub address: IpAddr,
+    /// Canonical name of the host.
+    pub name:    String,
+    /// Other names of the host.
+    pub aliases: Vec<String>,
+}
+
+/// The contents of a hosts file.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Hosts {
+    /// Entries in the order in which they appear.
+    pub entries: Vec<Entry>,
+}
+
+impl Hosts {
+    /// Parses the contents of a hosts file.
+    ///
+    /// Everything after a `#` is a comment. Lines without a valid address or without a
+    /// name are ignored. A scope ID after an IPv6 address is ignored.
+    pub fn parse(src: &str) -> Hosts {
+        let mut entries = vec!();
+        for line in src.lines() {
+            let line = match line.find('#') {
+                Some(pos) => &line[..pos],
+                _ => line,
+            };
+            let mut words = line.split_whitespace();
+            let address = match words.next() {
+                Some(a) => a.split('%').next().unwrap(),
+                _ => continue,
+            };
+            let address = match address.parse() {
+                Ok(a) => a,
+                _ => continue,
+            };
+            let name = match words.next() {
+                Some(n) => n.to_string(),
+                _ => continue,
+            };
+            entries.push(Entry {
+                address: address,
+                name:    name,
+                aliases: words.map(|w| w.to_string()).collect(),
+            });
+        }
+        Hosts { entries: entries }
+    }
+
+    /// Reads a hosts file.
+    pub fn read(path: &str) -> Result<Hosts, ()> {
+        Ok(Hosts::parse(&trycvt!(fs::read_to_string(path))))
+    }
+
+    /// Returns the addresses of a name in the order in which they appear.
+    ///
+    /// Canonical names and aliases are compared without regard to case. A trailing dot
+    /// is ignored.
+    pub fn addresses(&self, name: &str) -> Vec<IpAddr> {
+        let name = name.strip_suffix('.').unwrap_or(name);
+        let mut res = vec!();
+        for e in &self.entries {
+            let matches = e.name.eq_ignore_ascii_case(name)
+                || e.aliases.iter().any(|a| a.eq_ignore_ascii_case(name));
+            if matches && !res.contains(&e.address) {
+                res.push(e.address);
+            }
+        }
+        res
+    }
+
+    /// Returns the canonical names of an address in the order in which they appear.
+    pub fn names(&self, address: IpAddr) -> Vec<String> {
+        self.entries.iter().filter(|e| e.address == address).map(|e| e.name.clone())
+            .collect()
+    }
+}
+
+/// A hosts file that is read again when it changes.
+#[derive(Clone, Debug)]
+pub struct HostsFile {
+    path:     String,
+    modified: Option<(SystemTime, u64)>,
+    hosts:    Hosts,
+}
+
+impl HostsFile {
+    /// Creates a hosts file. The file is read by the first call to `get`.
+    pub fn new(path: &str) -> HostsFile {
+        HostsFile {
+            path:     path.to_string(),
+            modified: None,
+            hosts:    Hosts::default(),
+        }
+    }
+
+    /// Returns the contents of the file.
+    ///
+    /// The file is read again if its modification time or size has changed since it was
+    /// last read. If the file cannot be read, it is treated as empty.
+    pub fn get(&mut self) -> &Hosts {
+        let modified = fs::metadata(&self.path).ok().and_then(|m| {
+            m.modified().ok().map(|t| (t, m.len()))
+        });
+        if modified.is_none() || modified != self.modified {
+            self.hosts = Hosts::read(&self.path).unwrap_or_default();
+            self.modified = modified;
+        }
+        &self.hosts
+    }
+}
+
+/// A source of the `hosts` database in nsswitch.conf.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Source {
+    /// The hosts file.
+    Files,
+    /// The nameservers.
+    Dns,
+}
+
+/// Returns the sources of the `hosts` database of the contents of an nsswitch.conf
+/// file in the order in which they are consulted.
+///
+/// Services other than `files` and `dns` are ignored. If `files` or `dns` is followed by
+/// the action `[NOTFOUND=return]`, the later sources are dropped. Without a `hosts`
+/// line, the sources are `files` followed by `dns`.
+pub fn sources(nsswitch: &str) -> Vec<Source> {
+    for line in nsswitch.lines() {
+        let line = match line.find('#') {
+            Some(pos) => &line[..pos],
+            _ => line,
+        };
+        let spec = match line.trim_start().strip_prefix("hosts:") {
+            Some(s) => s,
+            _ => continue,
+        };
+        let mut res = vec!();
+        let mut last = None;
+        let mut words = spec.split_whitespace();
+        while let Some(word) = words.next() {
+            if word.starts_with('[') {
+                let mut action = word.to_string();
+                while !action.ends_with(']') {
+                    let w = match words.next() {
+                        Some(w) => w,
+                        _ => break,
+                    };
+                    action.push(' ');
+                    action.push_str(w);
+                }
+                if last.is_some() && returns_on_notfound(&action) {
+                    break;
+                }
+                continue;
+            }
+            last = match word {
+                "files" => Some(Source::Files),
+                "dns"   => Some(Source::Dns),
+                _       => None,
+            };
+            res.extend(last);
+        }
+        return res;
+    }
+    vec!(Source::Files, Source::Dns)
+}
+
+/// Returns the sources of the `hosts` database of the system.
+pub fn system_sources() -> Vec<Source> {
+    match fs::read_to_string("/etc/nsswitch.conf") {
+        Ok(s) => sources(&s),
+        _ => vec!(Source::Files, Source::Dns),
+    }
+}
+
+fn returns_on_notfound(action: &str) -> bool {
+    let action = action.trim_start_matches('[').trim_end_matches(']');
+    action.split_whitespace().any(|a| match a.split_once('=') {
+        Some((status, action)) => {
+            status.eq_ignore_ascii_case("NOTFOUND") && action.eq_ignore_ascii_case("return")
+        },
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTS: &str = "\
+# The loopback addresses.
+127.0.0.1   localhost
+::1         localhost ip6-localhost ip6-loopback # IPv6
+
+192.0.2.1\tmail.example.com  mail   smtp
+fe80::1%eth0 router.local
+192.0.2.2   www.example.com mail
+192.0.2.1   MAIL.example.com
+192.0.2.256 invalid.example.com
+192.0.2.3
+";
+
+    #[test]
+    fn parse() {
+        let hosts = Hosts::parse(HOSTS);
+        assert_eq!(hosts.entries.len(), 6);
+        assert_eq!(hosts.entries[1], Entry {
+            address: "::1".parse().unwrap(),
+            name:    "localhost".to_string(),
+            aliases: vec!("ip6-localhost".to_string(), "ip6-loopback".to_string()),
+        });
+        assert_eq!(hosts.entries[3].address, "fe80::1".parse::<IpAddr>().unwrap());
+        assert!(hosts.entries[3].aliases.is_empty());
+    }
+
+    #[test]
+    fn lookups() {
+        let hosts = Hosts::parse(HOSTS);
+        let ips = |ips: &[&str]| ips.iter().map(|s| s.parse().unwrap()).collect::<Vec<IpAddr>>();
+        assert_eq!(hosts.addresses("localhost"), ips(&["127.0.0.1", "::1"]));
+        assert_eq!(hosts.addresses("IP6-Loopback."), ips(&["::1"]));
+        assert_eq!(hosts.addresses("mail"), ips(&["192.0.2.1", "192.0.2.2"]));
+        assert_eq!(hosts.addresses("mail.example.com"), ips(&["192.0.2.1"]));
+        assert!(hosts.addresses("invalid.example.com").is_empty());
+        assert_eq!(hosts.names("192.0.2.1".parse().unwrap()),
+                   ["mail.example.com", "MAIL.example.com"]);
+        assert!(hosts.names("192.0.2.3".parse().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn reload() {
+        let path = std::env::temp_dir().join(format!("dns2-hosts-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut file = HostsFile::new(path);
+        assert!(file.get().entries.is_empty());
+        fs::write(path, "192.0.2.1 example.com\n").unwrap();
+        assert_eq!(file.get().addresses("example.com").len(), 1);
+        fs::write(path, "192.0.2.1 example.com\n192.0.2.2 example.com\n").unwrap();
+        assert_eq!(file.get().addresses("example.com").len(), 2);
+        fs::remove_file(path).unwrap();
+        assert!(file.get().entries.is_empty());
+    }
+
+    #[test]
+    fn nsswitch() {
+        use Source::{Files, Dns};
+        assert_eq!(sources(""), [Files, Dns]);
+        assert_eq!(sources("passwd: files\n# hosts: dns\n"), [Files, Dns]);
+        assert_eq!(sources("hosts: dns files\n"), [Dns, Files]);
+        // Unknown services and their actions are skipped.
+        let conf = "hosts: mymachines resolve [!UNAVAIL=return] files myhostname dns\n";
+        assert_eq!(sources(conf), [Files, Dns]);
+        assert_eq!(sources("hosts: files [NOTFOUND=return] dns\n"), [Files]);
+        assert_eq!(sources("hosts: files [notfound=Return] dns\n"), [Files]);
+        assert_eq!(sources("hosts: files [SUCCESS=continue NOTFOUND=return] dns\n"), [Files]);
+        assert_eq!(sources("hosts: files [UNAVAIL=return] dns\n"), [Files, Dns]);
+        assert_eq!(sources("hosts: [NOTFOUND=return] files dns\n"), [Files, Dns]);
+        assert!(sources("hosts: mdns4_minimal\n").is_empty());
+    }
+}
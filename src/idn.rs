@@ -0,0 +1,39 @@
+//! Internationalized domain names (IDNA 2008 with the UTS #46 mapping).
+//!
+//! Names in questions and records are sequences of octets that are compared without
+//! regard to ASCII case only. A name that contains non-ASCII characters, a U-label,
+//! must be converted to its A-label (`xn--` followed by the punycode of the label)
+//! before it is sent to a nameserver.
+
+use alloc::string::{String, ToString};
+
+use idna::uts46::{AsciiDenyList, DnsLength, Hyphens, Uts46};
+
+/// Converts a domain name to its ASCII form.
+///
+/// The name is mapped according to UTS #46 (e.g., lower-cased and normalized) and every
+/// label that contains non-ASCII characters is replaced by its A-label. ASCII labels
+/// other than the case mapping are left as they are, so underscore labels such as
+/// `_dmarc` are accepted.
+///
+/// Returns an error if the name contains disallowed characters, if a label is not a
+/// valid A-label or U-label, or if the result exceeds the length limits of RFC 1035.
+pub fn to_ascii(name: &str) -> Result<String, ()> {
+    let res = Uts46::new().to_ascii(name.as_bytes(), AsciiDenyList::EMPTY, Hyphens::Allow,
+                                    DnsLength::VerifyAllowRootDot);
+    match res {
+        Ok(name) => Ok(name.into_owned()),
+        Err(_) => Err(()),
+    }
+}
+
+/// Converts a domain name to its Unicode form for display.
+///
+/// Every A-label is replaced by the U-label it encodes. If the name is not a valid
+/// internationalized domain name, it is returned unchanged.
+pub fn to_unicode(name: &str) -> String {
+    match Uts46::new().to_unicode(name.as_bytes(), AsciiDenyList::EMPTY, Hyphens::Allow) {
+        (name, Ok(())) => name.into_owned(),
+        (_, Err(_)) => name.to_string(),
+    }
+}
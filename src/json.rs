@@ -0,0 +1,539 @@
+//! JSON representation of DNS messages.
+//!
+//! Converts packets to and from the JSON format of RFC 8427. Names are written in
+//! presentation form with a trailing dot. Every record has an `rdata*` member with the
+//! presentation form of its data as well as `RDLENGTH` and `RDATAHEX`, and the complete
+//! message is included as `messageOctetsHEX`. The `CD` member is omitted because packets
+//! do not carry the CD bit.
+//!
+//! When a message is read back, `messageOctetsHEX` is used if it is present. Otherwise
+//! the packet is assembled from the header members and the record arrays. Record data
+//! is taken from `RDATAHEX` or, for A, AAAA, MX, NS, CNAME, PTR, RP, SOA and TXT records,
+//! from the `rdata*` member. Records of unknown types are skipped.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::{Duration};
+
+use serde_json::{self, Value, Map};
+
+use crate::{Packet, Question, Record, Data, Type, Class, QueryKind, ResponseCode, Edns};
+use crate::{format, len, parse, OPT};
+
+/// Converts a packet to its JSON representation.
+pub fn to_value(p: &Packet) -> Value {
+    let mut m = Map::new();
+    m.insert("ID".to_string(), Value::from(p.id as u16));
+    m.insert("QR".to_string(), Value::from(!p.is_query));
+    m.insert("Opcode".to_string(), Value::from(p.kind as u8));
+    m.insert("AA".to_string(), Value::from(p.is_authoritative));
+    m.insert("TC".to_string(), Value::from(p.truncated));
+    m.insert("RD".to_string(), Value::from(p.recursion_desired));
+    m.insert("RA".to_string(), Value::from(p.recursion_available));
+    m.insert("AD".to_string(), Value::from(p.authentic_data));
+    m.insert("RCODE".to_string(), Value::from(p.response_code as u8));
+    let num_additional = p.additional.len() + p.edns.is_some() as usize;
+    m.insert("QDCOUNT".to_string(), Value::from(p.question.len()));
+    m.insert("ANCOUNT".to_string(), Value::from(p.answer.len()));
+    m.insert("NSCOUNT".to_string(), Value::from(p.authority.len()));
+    m.insert("ARCOUNT".to_string(), Value::from(num_additional));
+
+    let questions = p.question.iter().map(question).collect();
+    m.insert("questionRRs".to_string(), Value::Array(questions));
+    let answers = p.answer.iter().map(record).collect();
+    m.insert("answerRRs".to_string(), Value::Array(answers));
+    let authority = p.authority.iter().map(record).collect();
+    m.insert("authorityRRs".to_string(), Value::Array(authority));
+    let mut additional: Vec<_> = p.additional.iter().map(record).collect();
+    if let Some(ref e) = p.edns {
+        additional.push(opt(e));
+    }
+    m.insert("additionalRRs".to_string(), Value::Array(additional));
+
+    let mut buf = vec!(0; len::packet(p));
+    let ok = {
+        let mut dst = &mut buf[..];
+        format::packet(&mut dst, p).is_ok()
+    };
+    if ok {
+        m.insert("messageOctetsHEX".to_string(), Value::from(hex(&buf)));
+    }
+    Value::Object(m)
+}
+
+/// Converts a packet to a JSON string.
+pub fn to_string(p: &Packet) -> String {
+    to_value(p).to_string()
+}
+
+/// Reads a packet from its JSON representation.
+pub fn from_value(v: &Value) -> Result<Packet, ()> {
+    let m = match *v {
+        Value::Object(ref m) => m,
+        _ => return Err(()),
+    };
+    if let Some(octets) = m.get("messageOctetsHEX") {
+        let octets = unhex(octets.as_str().ok_or(())?)?;
+        return Packet::parse(&octets).map(|p| p.1);
+    }
+
+    let mut p = Packet::query(int(m, "ID")? as u16 as i16);
+    p.is_query = !flag(m, "QR")?;
+    p.kind = match int(m, "Opcode")? {
+        0 => QueryKind::Standard,
+        1 => QueryKind::Inverse,
+        2 => QueryKind::Status,
+        _ => return Err(()),
+    };
+    p.is_authoritative = flag(m, "AA")?;
+    p.truncated = flag(m, "TC")?;
+    p.recursion_desired = flag(m, "RD")?;
+    p.recursion_available = flag(m, "RA")?;
+    p.authentic_data = flag(m, "AD")?;
+    p.response_code = match int(m, "RCODE")? {
+        0 => ResponseCode::Ok,
+        1 => ResponseCode::FormatError,
+        2 => ResponseCode::ServerFailure,
+        3 => ResponseCode::NameError,
+        4 => ResponseCode::NotImplemented,
+        5 => ResponseCode::Refused,
+        6 => ResponseCode::NameExists,
+        7 => ResponseCode::RRsetExists,
+        8 => ResponseCode::RRsetMissing,
+        9 => ResponseCode::NotAuthoritative,
+        10 => ResponseCode::NotZone,
+        _ => return Err(()),
+    };
+
+    for q in array(m, "questionRRs")? {
+        let q = q.as_object().ok_or(())?;
+        let ty = parse::type_from_u16(int(q, "TYPE")? as u16);
+        let class = parse::class_from_u16(int(q, "CLASS")? as u16);
+        if let (Ok(ty), Ok(class)) = (ty, class) {
+            p.question.push(Question {
+                name:  name(q, "NAME")?,
+                ty:    ty,
+                class: class,
+            });
+        }
+    }
+    for &(key, additional) in &[("answerRRs", false), ("authorityRRs", false),
+                                ("additionalRRs", true)] {
+        for r in array(m, key)? {
+            let r = r.as_object().ok_or(())?;
+            let ty = int(r, "TYPE")? as u16;
+            if additional && ty == OPT {
+                p.edns = Some(edns(r)?);
+                continue;
+            }
+            let ty = parse::type_from_u16(ty);
+            let class = parse::class_from_u16(int(r, "CLASS")? as u16);
+            let (ty, class) = match (ty, class) {
+                (Ok(ty), Ok(class)) => (ty, class),
+                _ => continue,
+            };
+            let record = Record {
+                name:         name(r, "NAME")?,
+                class:        class,
+                time_to_live: Duration::from_secs(int(r, "TTL")?),
+                data:         data(r, ty)?,
+            };
+            match key {
+                "answerRRs"    => p.answer.push(record),
+                "authorityRRs" => p.authority.push(record),
+                _              => p.additional.push(record),
+            }
+        }
+    }
+    Ok(p)
+}
+
+/// Reads a packet from a JSON string.
+pub fn from_str(s: &str) -> Result<Packet, ()> {
+    let v: Value = trycvt!(serde_json::from_str(s));
+    from_value(&v)
+}
+
+fn question(q: &Question) -> Value {
+    let mut m = Map::new();
+    m.insert("NAME".to_string(), Value::from(fqdn(&q.name)));
+    m.insert("TYPE".to_string(), Value::from(q.ty as u16));
+    m.insert("TYPEname".to_string(), Value::from(type_name(q.ty)));
+    m.insert("CLASS".to_string(), Value::from(q.class as u16));
+    m.insert("CLASSname".to_string(), Value::from(class_name(q.class)));
+    Value::Object(m)
+}
+
+fn record(r: &Record) -> Value {
+    let ty = r.data.to_type();
+    let mut rdata = vec!(0; len::data(&r.data));
+    let ok = {
+        let mut dst = &mut rdata[..];
+        format::data(&mut dst, &r.data).is_ok()
+    };
+    let mut m = Map::new();
+    m.insert("NAME".to_string(), Value::from(fqdn(&r.name)));
+    m.insert("TYPE".to_string(), Value::from(ty as u16));
+    m.insert("TYPEname".to_string(), Value::from(type_name(ty)));
+    m.insert("CLASS".to_string(), Value::from(r.class as u16));
+    m.insert("CLASSname".to_string(), Value::from(class_name(r.class)));
+    m.insert("TTL".to_string(), Value::from(r.time_to_live.as_secs() as u32));
+    m.insert(format!("rdata{}", type_name(ty)), Value::from(presentation(&r.data)));
+    if ok {
+        m.insert("RDLENGTH".to_string(), Value::from(rdata.len()));
+        m.insert("RDATAHEX".to_string(), Value::from(hex(&rdata)));
+    }
+    Value::Object(m)
+}
+
+/// Writes the OPT pseudo-record. It has no presentation form.
+fn opt(e: &Edns) -> Value {
+    let mut rdata = vec!();
+    for &(code, ref data) in &e.options {
+        rdata.extend([(code >> 8) as u8, code as u8].iter().cloned());
+        rdata.extend([(data.len() >> 8) as u8, data.len() as u8].iter().cloned());
+        rdata.extend(data.iter().cloned());
+    }
+    let ttl = (e.extended_rcode as u32) << 24 | (e.version as u32) << 16
+        | (e.dnssec_ok as u32) << 15;
+    let mut m = Map::new();
+    m.insert("NAME".to_string(), Value::from("."));
+    m.insert("TYPE".to_string(), Value::from(OPT));
+    m.insert("TYPEname".to_string(), Value::from("OPT"));
+    m.insert("CLASS".to_string(), Value::from(e.udp_payload_size));
+    m.insert("TTL".to_string(), Value::from(ttl));
+    m.insert("RDLENGTH".to_string(), Value::from(rdata.len()));
+    m.insert("RDATAHEX".to_string(), Value::from(hex(&rdata)));
+    Value::Object(m)
+}
+
+fn edns(r: &Map<String, Value>) -> Result<Edns, ()> {
+    let rdata = match r.get("RDATAHEX") {
+        Some(v) => unhex(v.as_str().ok_or(())?)?,
+        _ => vec!(),
+    };
+    let class = int(r, "CLASS")? as u16;
+    let ttl = int(r, "TTL")? as u32;
+    let mut wire = vec!(0, (OPT >> 8) as u8, OPT as u8, (class >> 8) as u8, class as u8,
+                        (ttl >> 24) as u8, (ttl >> 16) as u8, (ttl >> 8) as u8, ttl as u8,
+                        (rdata.len() >> 8) as u8, rdata.len() as u8);
+    wire.extend(rdata);
+    match parse::opt(&mut &wire[..])? {
+        Some(e) => Ok(e),
+        _ => Err(()),
+    }
+}
+
+fn data(r: &Map<String, Value>, ty: Type) -> Result<Data, ()> {
+    if let Some(v) = r.get("RDATAHEX") {
+        let rdata = unhex(v.as_str().ok_or(())?)?;
+        return parse::data(ty, &rdata);
+    }
+    let s = match r.get(&format!("rdata{}", type_name(ty))).and_then(|v| v.as_str()) {
+        Some(s) => s,
+        _ => return Err(()),
+    };
+    let fields: Vec<&str> = s.split_whitespace().collect();
+    let data = match (ty, fields.len()) {
+        (Type::A, 1)     => Data::A(trycvt!(fields[0].parse::<Ipv4Addr>())),
+        (Type::Aaaa, 1)  => Data::Aaaa(trycvt!(fields[0].parse::<Ipv6Addr>())),
+        (Type::Mx, 2)    => Data::Mx(trycvt!(fields[0].parse()), unfqdn(fields[1])),
+        (Type::Ns, 1)    => Data::Ns(unfqdn(fields[0])),
+        (Type::Cname, 1) => Data::Cname(unfqdn(fields[0])),
+        (Type::Ptr, 1)   => Data::Ptr(unfqdn(fields[0])),
+        (Type::Rp, 2)    => Data::Rp(unfqdn(fields[0]), unfqdn(fields[1])),
+        (Type::Soa, 7)   => {
+            let mut n = [0u32; 5];
+            for (i, f) in fields[2..].iter().enumerate() {
+                n[i] = trycvt!(f.parse());
+            }
+            Data::Soa(unfqdn(fields[0]), unfqdn(fields[1]), n[0],
+                      Duration::from_secs(n[1] as u64), Duration::from_secs(n[2] as u64),
+                      Duration::from_secs(n[3] as u64), Duration::from_secs(n[4] as u64))
+        },
+        (Type::Txt, _)   => Data::Txt(unquote(s)?),
+        _ => return Err(()),
+    };
+    Ok(data)
+}
+
+fn int(m: &Map<String, Value>, key: &str) -> Result<u64, ()> {
+    m.get(key).and_then(|v| v.as_u64()).ok_or(())
+}
+
+/// Reads a boolean member. Missing flags are unset.
+fn flag(m: &Map<String, Value>, key: &str) -> Result<bool, ()> {
+    match m.get(key) {
+        Some(&Value::Bool(b)) => Ok(b),
+        Some(Value::Number(n)) if n.as_u64() == Some(0) => Ok(false),
+        Some(Value::Number(n)) if n.as_u64() == Some(1) => Ok(true),
+        None => Ok(false),
+        _ => Err(()),
+    }
+}
+
+/// Reads an array member. Missing arrays are empty.
+fn array<'a>(m: &'a Map<String, Value>, key: &str) -> Result<&'a [Value], ()> {
+    match m.get(key) {
+        Some(Value::Array(a)) => Ok(a),
+        None => Ok(&[]),
+        _ => Err(()),
+    }
+}
+
+fn name(m: &Map<String, Value>, key: &str) -> Result<String, ()> {
+    m.get(key).and_then(|v| v.as_str()).map(unfqdn).ok_or(())
+}
+
+fn fqdn(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+fn unfqdn(name: &str) -> String {
+    name.trim_end_matches('.').to_string()
+}
+
+/// Returns the presentation form of the data.
+fn presentation(d: &Data) -> String {
+    match *d {
+        Data::A(ip)                  => ip.to_string(),
+        Data::Aaaa(ip)               => ip.to_string(),
+        Data::Mx(pref, ref domain)   => format!("{} {}", pref, fqdn(domain)),
+        Data::Ns(ref domain)         => fqdn(domain),
+        Data::Cname(ref domain)      => fqdn(domain),
+        Data::Ptr(ref domain)        => fqdn(domain),
+        Data::Rp(ref mbox, ref txt)  => format!("{} {}", fqdn(mbox), fqdn(txt)),
+        Data::Soa(ref mname, ref rname, serial, refresh, retry, expire, minimum) => {
+            format!("{} {} {} {} {} {} {}", fqdn(mname), fqdn(rname), serial,
+                    refresh.as_secs(), retry.as_secs(), expire.as_secs(),
+                    minimum.as_secs())
+        },
+        Data::Txt(ref strings) => {
+            let strings: Vec<_> = strings.iter().map(|s| quote(s)).collect();
+            strings.join(" ")
+        },
+        Data::Ds(tag, alg, ty, ref digest) | Data::Cds(tag, alg, ty, ref digest) => {
+            format!("{} {} {} {}", tag, alg, ty, hex(digest))
+        },
+        Data::Dnskey(flags, proto, alg, ref key) | Data::Cdnskey(flags, proto, alg, ref key) => {
+            format!("{} {} {} {}", flags, proto, alg, base64(key))
+        },
+        Data::Csync(serial, flags, ref types) => {
+            let mut s = format!("{} {}", serial, flags);
+            for &ty in types {
+                s.push(' ');
+                s.push_str(type_name(ty));
+            }
+            s
+        },
+        Data::Zonemd(serial, scheme, hash, ref digest) => {
+            format!("{} {} {} {}", serial, scheme, hash, hex(digest))
+        },
+    }
+}
+
+fn type_name(ty: Type) -> &'static str {
+    match ty {
+        Type::A       => "A",
+        Type::Aaaa    => "AAAA",
+        Type::Mx      => "MX",
+        Type::Ns      => "NS",
+        Type::Cname   => "CNAME",
+        Type::Ptr     => "PTR",
+        Type::Rp      => "RP",
+        Type::Soa     => "SOA",
+        Type::Txt     => "TXT",
+        Type::Ds      => "DS",
+        Type::Dnskey  => "DNSKEY",
+        Type::Cds     => "CDS",
+        Type::Cdnskey => "CDNSKEY",
+        Type::Csync   => "CSYNC",
+        Type::Zonemd  => "ZONEMD",
+        Type::All     => "ANY",
+    }
+}
+
+fn class_name(class: Class) -> &'static str {
+    match class {
+        Class::In   => "IN",
+        Class::Ch   => "CH",
+        Class::Hs   => "HS",
+        Class::None => "NONE",
+        Class::All  => "ANY",
+    }
+}
+
+/// Writes a character-string in quotes. Quotes, backslashes and non-printable bytes are
+/// escaped.
+fn quote(s: &[u8]) -> String {
+    let mut res = "\"".to_string();
+    for &b in s {
+        match b {
+            b'"' | b'\\' => { res.push('\\'); res.push(b as char); },
+            0x20..=0x7e  => res.push(b as char),
+            _            => res.push_str(&format!("\\{:03}", b)),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// Reads a sequence of quoted or unquoted character-strings.
+fn unquote(s: &str) -> Result<Vec<Vec<u8>>, ()> {
+    let s = s.as_bytes();
+    let mut res = vec!();
+    let mut i = 0;
+    loop {
+        while i < s.len() && s[i] == b' ' {
+            i += 1;
+        }
+        if i == s.len() {
+            return Ok(res);
+        }
+        let quoted = s[i] == b'"';
+        if quoted {
+            i += 1;
+        }
+        let mut cur = vec!();
+        loop {
+            if i == s.len() {
+                if quoted {
+                    return Err(());
+                }
+                break;
+            }
+            match s[i] {
+                b'"' if quoted => { i += 1; break; },
+                b' ' if !quoted => break,
+                b'\\' => {
+                    if i + 3 < s.len() && s[i+1..i+4].iter().all(|c| b'0' <= *c && *c <= b'9') {
+                        let v = s[i+1..i+4].iter().fold(0u32, |v, c| v * 10 + (c - b'0') as u32);
+                        if v > 255 {
+                            return Err(());
+                        }
+                        cur.push(v as u8);
+                        i += 4;
+                    } else if i + 1 < s.len() {
+                        cur.push(s[i+1]);
+                        i += 2;
+                    } else {
+                        return Err(());
+                    }
+                },
+                c => { cur.push(c); i += 1; },
+            }
+        }
+        res.push(cur);
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    const DIGITS: &[u8] = b"0123456789ABCDEF";
+    let mut res = String::with_capacity(2 * data.len());
+    for &b in data {
+        res.push(DIGITS[(b >> 4) as usize] as char);
+        res.push(DIGITS[(b & 0xf) as usize] as char);
+    }
+    res
+}
+
+fn unhex(s: &str) -> Result<Vec<u8>, ()> {
+    let s = s.as_bytes();
+    if s.len() % 2 != 0 {
+        return Err(());
+    }
+    let mut res = Vec::with_capacity(s.len() / 2);
+    for pair in s.chunks(2) {
+        let mut b = 0;
+        for &c in pair {
+            let v = match c {
+                b'0'..=b'9' => c - b'0',
+                b'a'..=b'f' => c - b'a' + 10,
+                b'A'..=b'F' => c - b'A' + 10,
+                _ => return Err(()),
+            };
+            b = b << 4 | v;
+        }
+        res.push(b);
+    }
+    Ok(res)
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut res = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let mut acc = 0u32;
+        for (i, &b) in chunk.iter().enumerate() {
+            acc |= (b as u32) << (16 - 8 * i);
+        }
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(ALPHABET[(acc >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response() -> Packet {
+        let mut p = Packet::query(1);
+        p.is_query = false;
+        p.question.push(Question {
+            name:  "example.com".to_string(),
+            ty:    Type::Mx,
+            class: Class::In,
+        });
+        let data = [Data::Mx(0, "".to_string()), Data::Ns("ns.example.com".to_string())];
+        for data in data {
+            p.answer.push(Record {
+                name:         "example.com".to_string(),
+                class:        Class::In,
+                time_to_live: Duration::from_secs(60),
+                data:         data,
+            });
+        }
+        p
+    }
+
+    #[test]
+    fn round_trip() {
+        let p = response();
+        assert_eq!(from_value(&to_value(&p)), Ok(p.clone()));
+        let mut v = to_value(&p);
+        v.as_object_mut().unwrap().remove("messageOctetsHEX");
+        assert_eq!(from_value(&v), Ok(p.clone()));
+        for r in v["answerRRs"].as_array_mut().unwrap() {
+            r.as_object_mut().unwrap().remove("RDATAHEX");
+        }
+        assert_eq!(from_value(&v), Ok(p));
+    }
+
+    #[test]
+    fn header() {
+        let mut p = response();
+        p.authentic_data = true;
+        p.response_code = ResponseCode::NotZone;
+        let mut v = to_value(&p);
+        assert_eq!(v["AD"], true);
+        assert_eq!(v["RCODE"], 10);
+        assert!(v.get("CD").is_none());
+        v.as_object_mut().unwrap().remove("messageOctetsHEX");
+        assert_eq!(from_value(&v), Ok(p));
+        v["messageOctetsHEX"] = Value::from("000");
+        assert_eq!(from_value(&v), Err(()));
+    }
+
+    #[test]
+    fn null_mx() {
+        let v = to_value(&response());
+        assert_eq!(v["answerRRs"][0]["RDATAHEX"], "000000");
+        assert_eq!(v["answerRRs"][0]["rdataMX"], "0 .");
+    }
+}
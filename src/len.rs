@@ -1,6 +1,7 @@
-use std::iter::{AdditiveIterator};
 
-use {Data, Packet, Record, Question};
+use alloc::vec::{Vec};
+
+use crate::{Data, Packet, Record, Question, Type, Edns};
 
 pub fn packet(p: &Packet) -> usize {
     let mut len = 12; // Header size
@@ -8,25 +9,48 @@ pub fn packet(p: &Packet) -> usize {
     for r in &p.answer     { len += record(r);   }
     for r in &p.authority  { len += record(r);   }
     for r in &p.additional { len += record(r);   }
+    if let Some(ref e) = p.edns { len += opt(e); }
     len
 }
 
-fn question(q: &Question) -> usize {
-    domain_name(&q.name) + 4
+pub fn opt(e: &Edns) -> usize {
+    let options: usize = e.options.iter().map(|o| 4 + o.1.len()).sum();
+    1 + 2 + 2 + 4 + 2 + options
+}
+
+pub fn question(q: &Question) -> usize {
+    question_raw(&q.name)
 }
 
-fn record(r: &Record) -> usize {
+pub fn question_raw(name: &str) -> usize {
+    domain_name(name) + 4
+}
+
+pub fn record(r: &Record) -> usize {
     domain_name(&r.name) + 2 + 2 + 4 + 2 + data(&r.data)
 }
 
+pub fn record_raw(name: &str, rdata: &[u8]) -> usize {
+    domain_name(name) + 2 + 2 + 4 + 2 + rdata.len()
+}
+
 pub fn data(d: &Data) -> usize {
     match *d {
-        Data::A(..)                 => a(),
-        Data::Aaaa(..)              => aaaa(),
-        Data::Mx(_, ref domain)     => mx(domain),
-        Data::Ptr(ref domain)       => ptr(domain),
-        Data::Rp(ref mbox, ref txt) => rp(mbox, txt),
-        Data::Txt(ref text)         => txt(text),
+        Data::A(..)                         => a(),
+        Data::Aaaa(..)                      => aaaa(),
+        Data::Mx(_, ref domain)             => mx(domain),
+        Data::Ns(ref domain)                => ns(domain),
+        Data::Cname(ref domain)             => cname(domain),
+        Data::Ptr(ref domain)               => ptr(domain),
+        Data::Rp(ref mbox, ref txt)         => rp(mbox, txt),
+        Data::Soa(ref mname, ref rname, ..) => soa(mname, rname),
+        Data::Txt(ref text)                 => txt(text),
+        Data::Ds(_, _, _, ref rest)         => key_or_digest(rest),
+        Data::Dnskey(_, _, _, ref rest)     => key_or_digest(rest),
+        Data::Cds(_, _, _, ref rest)        => key_or_digest(rest),
+        Data::Cdnskey(_, _, _, ref rest)    => key_or_digest(rest),
+        Data::Csync(_, _, ref types)        => csync(types),
+        Data::Zonemd(_, _, _, ref digest)   => zonemd(digest),
     }
 }
 
@@ -42,6 +66,14 @@ fn mx(domain: &str) -> usize {
     2 + domain_name(domain)
 }
 
+fn ns(domain: &str) -> usize {
+    domain_name(domain)
+}
+
+fn cname(domain: &str) -> usize {
+    domain_name(domain)
+}
+
 fn ptr(domain: &str) -> usize {
     domain_name(domain)
 }
@@ -50,19 +82,62 @@ fn rp(mbox: &str, txt: &str) -> usize {
     domain_name(mbox) + domain_name(txt)
 }
 
-fn txt(s: &[String]) -> usize {
+fn soa(mname: &str, rname: &str) -> usize {
+    domain_name(mname) + domain_name(rname) + 5 * 4
+}
+
+fn key_or_digest(rest: &[u8]) -> usize {
+    2 + 1 + 1 + rest.len()
+}
+
+fn csync(types: &[Type]) -> usize {
+    4 + 2 + type_bitmap(types).len()
+}
+
+/// Returns the type bitmap of NSEC and CSYNC records.
+pub fn type_bitmap(types: &[Type]) -> Vec<u8> {
+    let mut types: Vec<u16> = types.iter().map(|&t| t as u16).collect();
+    types.sort();
+    types.dedup();
+    let mut res = vec!();
+    let mut i = 0;
+    while i < types.len() {
+        let window = types[i] >> 8;
+        let mut bitmap = [0u8; 32];
+        let mut len = 0;
+        while i < types.len() && types[i] >> 8 == window {
+            let bit = (types[i] & 0xff) as usize;
+            bitmap[bit / 8] |= 0x80 >> (bit % 8);
+            len = bit / 8 + 1;
+            i += 1;
+        }
+        res.push(window as u8);
+        res.push(len as u8);
+        res.extend(bitmap[..len].iter().cloned());
+    }
+    res
+}
+
+fn zonemd(digest: &[u8]) -> usize {
+    4 + 1 + 1 + digest.len()
+}
+
+fn txt(s: &[Vec<u8>]) -> usize {
     s.iter().map(|v| character_string(v)).sum()
 }
 
 fn domain_name(s: &str) -> usize {
+    let s = s.strip_suffix('.').unwrap_or(s);
     let mut len = 0;
-    for part in s.split('.') {
-        len += 1 + part.len();
+    if !s.is_empty() {
+        for part in s.split('.') {
+            len += 1 + part.len();
+        }
     }
     len += 1;
     len
 }
 
-fn character_string(s: &str) -> usize {
+fn character_string(s: &[u8]) -> usize {
     1 + s.len()
 }
@@ -14,44 +14,106 @@
 //!     class: Class::In
 //! });
 //! let mut buf = [0; 512];
-//! let len = try!(packet.format(&mut buf));
+//! let len = packet.format(&mut buf)?;
 //! ```
 //!
 //! # Toy functions
 //!
 //! On Linux it also provides a toy API for hostname resolution and other things.
+//!
+//! # no_std
+//!
+//! Without the default `std` feature the crate is `no_std` and only requires `alloc`.
+//! The `toy`, `spf`, `resolv`, `hosts` and `cache` modules are not available in this
+//! configuration.
+//! `MessageWriter::question_raw` and `MessageWriter::record_raw` format a message into
+//! a fixed buffer without allocating.
+//!
+//! # Serde
+//!
+//! With the `serde` feature, packets, questions, records and their parts implement
+//! `Serialize` and `Deserialize`. Types, classes, opcodes and response codes use their
+//! presentation names (`"AAAA"`, `"IN"`, `"NXDOMAIN"`) and TTLs are written in seconds.
+//!
+//! With the `json` feature, the `json` module converts packets to and from the JSON
+//! representation of RFC 8427.
+//!
+//! # Internationalized domain names
+//!
+//! Names are used as they are; a name with non-ASCII characters is formatted as its raw
+//! UTF-8 bytes. With the `idna` feature, the `idn` module converts names between their
+//! Unicode and ASCII (punycode) forms and `Question::idn` creates a question for a
+//! Unicode name.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+// Struct literals spell out `field: field` to keep the columns aligned, and errors
+// without further information are reported as `Err(())`.
+#![allow(clippy::redundant_field_names, clippy::result_unit_err)]
+
+#[cfg_attr(not(feature = "std"), macro_use)] extern crate alloc;
 
-#![crate_type = "lib"]
-#![crate_name = "dns2"]
-#![feature(core)]
-#![feature(io)]
-#![feature(net)]
-#![feature(std_misc)]
-#![feature(libc)]
-#![feature(fs)]
+use core::time::{Duration};
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-extern crate libc;
+use alloc::vec::{Vec};
+use alloc::string::{String, ToString};
 
-use std::time::{Duration};
-use std::net::{Ipv4Addr, Ipv6Addr};
+#[cfg(feature = "serde")] use serde::{Serialize, Deserialize};
 
 #[macro_use] mod rust;
 mod parse;
 mod len;
 mod format;
-#[cfg(target_os = "linux")] pub mod toy;
+mod writer;
+mod rrset;
+mod answers;
+mod canonical;
+mod sha2;
+mod tags;
+#[cfg(feature = "serde")] mod seconds;
+#[cfg(all(feature = "std", target_os = "linux"))] pub mod toy;
+#[cfg(all(feature = "std", target_os = "linux"))] mod random;
+#[cfg(feature = "std")] pub mod spf;
+#[cfg(feature = "std")] pub mod resolv;
+#[cfg(feature = "std")] pub mod hosts;
+#[cfg(feature = "std")] pub mod cache;
+pub mod dmarc;
+pub mod dkim;
+pub mod zonemd;
+pub mod delegation;
+pub mod reverse;
+pub mod selection;
+#[cfg(feature = "json")] pub mod json;
+#[cfg(feature = "idna")] pub mod idn;
 
-const IN: u16 = 1;
+pub use writer::{MessageWriter, Overflow, Section};
+pub use rrset::{RRset};
 
-const A:    u16 = 1;
-const AAAA: u16 = 28;
-const MX:   u16 = 15;
-const PTR:  u16 = 12;
-const RP:   u16 = 17;
-const TXT:  u16 = 16;
-const ALL:  u16 = 255;
+const IN:   u16 = 1;
+const CH:   u16 = 3;
+const HS:   u16 = 4;
+const NONE: u16 = 254;
+
+const A:       u16 = 1;
+const AAAA:    u16 = 28;
+const MX:      u16 = 15;
+const NS:      u16 = 2;
+const CNAME:   u16 = 5;
+const PTR:     u16 = 12;
+const RP:      u16 = 17;
+const SOA:     u16 = 6;
+const TXT:     u16 = 16;
+const DS:      u16 = 43;
+const DNSKEY:  u16 = 48;
+const CDS:     u16 = 59;
+const CDNSKEY: u16 = 60;
+const CSYNC:   u16 = 62;
+const ZONEMD:  u16 = 63;
+const OPT:     u16 = 41;
+const ALL:     u16 = 255;
 
 /// A DNS packet.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Packet {
     /// ID of the packet.
@@ -68,6 +130,10 @@ pub struct Packet {
     pub recursion_desired:   bool,
     /// Set if recursion is available.
     pub recursion_available: bool,
+    /// Set if all data in the answer and authority sections has been validated with
+    /// DNSSEC (RFC 4035 section 3.2.3). In a query, set to request the bit in the
+    /// response (RFC 6840 section 5.7).
+    pub authentic_data:      bool,
     /// Response code.
     pub response_code:       ResponseCode,
 
@@ -79,6 +145,9 @@ pub struct Packet {
     pub authority:  Vec<Record>,
     /// Additional information.
     pub additional: Vec<Record>,
+
+    /// EDNS information. Stored in the additional section on the wire.
+    pub edns: Option<Edns>,
 }
 
 impl Packet {
@@ -111,10 +180,102 @@ impl Packet {
             return Err(FormatError::Buffer(len));
         }
         let back = dst.as_ptr() as usize;
-        try!(format::packet(&mut dst, self));
+        format::packet(&mut dst, self)?;
         Ok(dst.as_ptr() as usize - back)
     }
 
+    /// Groups the records of a section into RRsets.
+    ///
+    /// See `RRset::group` for details.
+    pub fn rrsets(&self, section: Section) -> Vec<RRset> {
+        let records = match section {
+            Section::Answer     => &self.answer,
+            Section::Authority  => &self.authority,
+            Section::Additional => &self.additional,
+        };
+        RRset::group(records)
+    }
+
+    /// Returns the answers of type `ty` for `name`.
+    ///
+    /// CNAME records in the answer section are followed starting at `name`. Only
+    /// records whose owner is `name` or one of the aliases reached from it are
+    /// returned; other records in the answer section are ignored. Names are compared
+    /// without regard to case. `Type::All` returns the records of all types, including
+    /// the CNAME records of the chain.
+    pub fn answers_for(&self, name: &str, ty: Type) -> Vec<&Record> {
+        answers::records(&self.answer, name, ty)
+    }
+
+    /// Returns the canonical name of `name`, i.e., the end of the chain of CNAME records
+    /// in the answer section that starts at `name`.
+    pub fn canonical_name(&self, name: &str) -> String {
+        let chain = answers::chain(&self.answer, name);
+        chain[chain.len() - 1].to_string()
+    }
+
+    /// Returns the answers of type `ty` for the name of the first question.
+    fn answers(&self, ty: Type) -> Vec<&Record> {
+        match self.question.first() {
+            Some(q) => self.answers_for(&q.name, ty),
+            None => vec!(),
+        }
+    }
+
+    /// Returns the IPv4 addresses of the name of the first question.
+    ///
+    /// See `answers_for` for how the records are selected.
+    pub fn ipv4s(&self) -> impl Iterator<Item = Ipv4Addr> + '_ {
+        self.answers(Type::A).into_iter().filter_map(|r| match r.data {
+            Data::A(addr) => Some(addr),
+            _ => None,
+        })
+    }
+
+    /// Returns the IPv6 addresses of the name of the first question.
+    pub fn ipv6s(&self) -> impl Iterator<Item = Ipv6Addr> + '_ {
+        self.answers(Type::Aaaa).into_iter().filter_map(|r| match r.data {
+            Data::Aaaa(addr) => Some(addr),
+            _ => None,
+        })
+    }
+
+    /// Returns the IPv4 and IPv6 addresses of the name of the first question.
+    pub fn ips(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.ipv4s().map(IpAddr::V4).chain(self.ipv6s().map(IpAddr::V6))
+    }
+
+    /// Returns the preferences and exchanges of the MX records of the name of the first
+    /// question.
+    pub fn mx_records(&self) -> impl Iterator<Item = (i16, &str)> + '_ {
+        self.answers(Type::Mx).into_iter().filter_map(|r| match r.data {
+            Data::Mx(preference, ref exchange) => Some((preference, &exchange[..])),
+            _ => None,
+        })
+    }
+
+    /// Returns the nameservers of the NS records of the name of the first question.
+    pub fn ns_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.answers(Type::Ns).into_iter().filter_map(|r| match r.data {
+            Data::Ns(ref domain) => Some(&domain[..]),
+            _ => None,
+        })
+    }
+
+    /// Returns the targets of the PTR records of the name of the first question.
+    pub fn ptr_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.answers(Type::Ptr).into_iter().filter_map(|r| match r.data {
+            Data::Ptr(ref domain) => Some(&domain[..]),
+            _ => None,
+        })
+    }
+
+    /// Returns the TXT records of the name of the first question. The
+    /// character-strings of each record are concatenated (see `Data::txt`).
+    pub fn txt_records(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.answers(Type::Txt).into_iter().filter_map(|r| r.data.txt())
+    }
+
     /// Creates a new packet that has all header values preset for a query.
     pub fn query(id: i16) -> Packet {
         Packet {
@@ -125,20 +286,174 @@ impl Packet {
             truncated:           false,
             recursion_desired:   true,
             recursion_available: false,
+            authentic_data:      false,
             response_code:       ResponseCode::Ok,
 
             question: vec!(),
             answer: vec!(),
             authority: vec!(),
             additional: vec!(),
+
+            edns: None,
+        }
+    }
+
+    /// Creates a builder for a response to `query`.
+    ///
+    /// The response has the ID, kind, questions and recursion desired flag of the query.
+    /// If the query uses EDNS, so does the response and the DO bit is copied.
+    pub fn response_to(query: &Packet) -> ResponseBuilder {
+        let edns = query.edns.as_ref().map(|e| Edns {
+            udp_payload_size: DEFAULT_UDP_PAYLOAD_SIZE,
+            extended_rcode:   0,
+            version:          0,
+            dnssec_ok:        e.dnssec_ok,
+            options:          vec!(),
+        });
+        ResponseBuilder {
+            packet: Packet {
+                id:                  query.id,
+                is_query:            false,
+                kind:                query.kind,
+                is_authoritative:    false,
+                truncated:           false,
+                recursion_desired:   query.recursion_desired,
+                recursion_available: false,
+                authentic_data:      false,
+                response_code:       ResponseCode::Ok,
+
+                question: query.question.clone(),
+                answer: vec!(),
+                authority: vec!(),
+                additional: vec!(),
+
+                edns: edns,
+            },
+        }
+    }
+}
+
+/// The UDP payload size advertised in responses created by `Packet::response_to`.
+const DEFAULT_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+/// A builder for response packets.
+///
+/// Created by `Packet::response_to`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ResponseBuilder {
+    packet: Packet,
+}
+
+impl ResponseBuilder {
+    /// Sets whether the answer is authoritative.
+    pub fn authoritative(mut self, authoritative: bool) -> ResponseBuilder {
+        self.packet.is_authoritative = authoritative;
+        self
+    }
+
+    /// Sets whether recursion is available.
+    pub fn recursion_available(mut self, available: bool) -> ResponseBuilder {
+        self.packet.recursion_available = available;
+        self
+    }
+
+    /// Sets whether the data in the response has been validated with DNSSEC.
+    pub fn authentic_data(mut self, authentic: bool) -> ResponseBuilder {
+        self.packet.authentic_data = authentic;
+        self
+    }
+
+    /// Sets whether the response has been truncated.
+    pub fn truncated(mut self, truncated: bool) -> ResponseBuilder {
+        self.packet.truncated = truncated;
+        self
+    }
+
+    /// Sets the response code.
+    pub fn response_code(mut self, code: ResponseCode) -> ResponseBuilder {
+        self.packet.response_code = code;
+        self
+    }
+
+    /// Sets the response code to `NameError`, i.e., the name does not exist.
+    pub fn nxdomain(self) -> ResponseBuilder {
+        self.response_code(ResponseCode::NameError)
+    }
+
+    /// Sets the response code to `Refused`.
+    pub fn refused(self) -> ResponseBuilder {
+        self.response_code(ResponseCode::Refused)
+    }
+
+    /// Sets the response code to `ServerFailure`.
+    pub fn servfail(self) -> ResponseBuilder {
+        self.response_code(ResponseCode::ServerFailure)
+    }
+
+    /// Sets the response code to `FormatError`.
+    pub fn formerr(self) -> ResponseBuilder {
+        self.response_code(ResponseCode::FormatError)
+    }
+
+    /// Sets the response code to `NotImplemented`.
+    pub fn notimp(self) -> ResponseBuilder {
+        self.response_code(ResponseCode::NotImplemented)
+    }
+
+    /// Sets the UDP payload size advertised via EDNS. Has no effect if the query did not
+    /// use EDNS.
+    pub fn udp_payload_size(mut self, size: u16) -> ResponseBuilder {
+        if let Some(ref mut e) = self.packet.edns {
+            e.udp_payload_size = size;
         }
+        self
+    }
+
+    /// Adds a record to the answer section.
+    pub fn answer(mut self, record: Record) -> ResponseBuilder {
+        self.packet.answer.push(record);
+        self
+    }
+
+    /// Adds a record to the authority section.
+    pub fn authority(mut self, record: Record) -> ResponseBuilder {
+        self.packet.authority.push(record);
+        self
+    }
+
+    /// Adds a record to the additional section.
+    pub fn additional(mut self, record: Record) -> ResponseBuilder {
+        self.packet.additional.push(record);
+        self
+    }
+
+    /// Returns the response.
+    pub fn build(self) -> Packet {
+        self.packet
     }
 }
 
+/// EDNS information from the OPT pseudo-record (RFC 6891).
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Edns {
+    /// Maximum UDP payload size the sender can receive.
+    pub udp_payload_size: u16,
+    /// Upper 8 bits of the extended response code.
+    pub extended_rcode:   u8,
+    /// EDNS version.
+    pub version:          u8,
+    /// Set if DNSSEC records are requested.
+    pub dnssec_ok:        bool,
+    /// Options as code and data.
+    pub options:          Vec<(u16, Vec<u8>)>,
+}
+
 /// An error that can occur during formatting.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum FormatError {
-    /// The size of the packet would exceed 512 bytes.
+    /// The size of the packet would exceed 512 bytes, the record data would exceed 65535
+    /// bytes, or a section would have more than 65535 entries.
     Size,
     /// The buffer is too small. The argument is the required buffer size.
     Buffer(usize),
@@ -146,67 +461,127 @@ pub enum FormatError {
     Label(usize),
     /// A character string is larger than 255 bytes.
     String(usize),
+    /// A question or record was added after a later section had been started.
+    Section,
 }
 
 /// The kind of the query.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum QueryKind {
     /// Standard query.
+    #[cfg_attr(feature = "serde", serde(rename = "QUERY"))]
     Standard = 0,
     /// Inverse query.
+    #[cfg_attr(feature = "serde", serde(rename = "IQUERY"))]
     Inverse  = 1,
     /// Server status request.
+    #[cfg_attr(feature = "serde", serde(rename = "STATUS"))]
     Status   = 2,
 }
 
 /// The response code.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum ResponseCode {
     /// No error.
-    Ok             = 0,
+    #[cfg_attr(feature = "serde", serde(rename = "NOERROR"))]
+    Ok               = 0,
     /// Format error.
-    FormatError    = 1,
+    #[cfg_attr(feature = "serde", serde(rename = "FORMERR"))]
+    FormatError      = 1,
     /// Server failure.
-    ServerFailure  = 2,
+    #[cfg_attr(feature = "serde", serde(rename = "SERVFAIL"))]
+    ServerFailure    = 2,
     /// Name error.
-    NameError      = 3,
+    #[cfg_attr(feature = "serde", serde(rename = "NXDOMAIN"))]
+    NameError        = 3,
     /// Not implemented.
-    NotImplemented = 4,
+    #[cfg_attr(feature = "serde", serde(rename = "NOTIMP"))]
+    NotImplemented   = 4,
     /// Refused.
-    Refused        = 5,
+    #[cfg_attr(feature = "serde", serde(rename = "REFUSED"))]
+    Refused          = 5,
+    /// A name exists that should not (RFC 2136 section 2.2).
+    #[cfg_attr(feature = "serde", serde(rename = "YXDOMAIN"))]
+    NameExists       = 6,
+    /// An RRset exists that should not (RFC 2136 section 2.2).
+    #[cfg_attr(feature = "serde", serde(rename = "YXRRSET"))]
+    RRsetExists      = 7,
+    /// An RRset that should exist does not (RFC 2136 section 2.2).
+    #[cfg_attr(feature = "serde", serde(rename = "NXRRSET"))]
+    RRsetMissing     = 8,
+    /// The server is not authoritative for the zone (RFC 2136 section 2.2) or the
+    /// request is not authorized (RFC 8945 section 6).
+    #[cfg_attr(feature = "serde", serde(rename = "NOTAUTH"))]
+    NotAuthoritative = 9,
+    /// A name is not in the zone (RFC 2136 section 2.2).
+    #[cfg_attr(feature = "serde", serde(rename = "NOTZONE"))]
+    NotZone          = 10,
 }
 
 /// Type of the record or question.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 #[repr(u16)]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
 pub enum Type {
     /// IPv4 address.
-    A    = A,
+    A       = A,
     /// IPv6 address.
-    Aaaa = AAAA,
+    Aaaa    = AAAA,
     /// Mail exchange.
-    Mx   = MX,
+    Mx      = MX,
+    /// Authoritative nameserver.
+    Ns      = NS,
+    /// Canonical name of an alias.
+    Cname   = CNAME,
     /// Pointer to a domain name.
-    Ptr  = PTR,
+    Ptr     = PTR,
     /// Responsible person.
-    Rp   = RP,
+    Rp      = RP,
+    /// Start of a zone of authority.
+    Soa     = SOA,
     /// Text.
-    Txt  = TXT,
+    Txt     = TXT,
+    /// Delegation signer.
+    Ds      = DS,
+    /// DNSSEC public key.
+    Dnskey  = DNSKEY,
+    /// Child copy of a delegation signer.
+    Cds     = CDS,
+    /// Child copy of a DNSSEC public key.
+    Cdnskey = CDNSKEY,
+    /// Child-to-parent synchronization.
+    Csync   = CSYNC,
+    /// Message digest for DNS zones.
+    Zonemd  = ZONEMD,
     /// All.
-    All  = ALL,
+    #[cfg_attr(feature = "serde", serde(rename = "ANY"))]
+    All     = ALL,
 }
 
 /// Class of the request.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 #[repr(u16)]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
 pub enum Class {
     /// Internet.
-    In  = IN,
+    In   = IN,
+    /// Chaos.
+    Ch   = CH,
+    /// Hesiod.
+    Hs   = HS,
+    /// None. Used in dynamic updates.
+    None = NONE,
     /// All.
-    All = ALL,
+    #[cfg_attr(feature = "serde", serde(rename = "ANY"))]
+    All  = ALL,
 }
 
 /// A question.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Question {
     /// Domain name.
@@ -217,7 +592,22 @@ pub struct Question {
     pub class: Class,
 }
 
+impl Question {
+    /// Creates a question for a name that may contain non-ASCII characters.
+    ///
+    /// The name is converted to its ASCII form with `idn::to_ascii`.
+    #[cfg(feature = "idna")]
+    pub fn idn(name: &str, ty: Type, class: Class) -> Result<Question, ()> {
+        Ok(Question {
+            name:  idn::to_ascii(name)?,
+            ty:    ty,
+            class: class,
+        })
+    }
+}
+
 /// A record.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Record {
     /// Domain name.
@@ -225,13 +615,16 @@ pub struct Record {
     /// Class.
     pub class:        Class,
     /// Time to live.
+    #[cfg_attr(feature = "serde", serde(with = "seconds"))]
     pub time_to_live: Duration,
     /// Data.
     pub data:         Data,
 }
 
 /// Record data.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
 pub enum Data {
     /// IPv4 address.
     A(Ipv4Addr),
@@ -239,24 +632,72 @@ pub enum Data {
     Aaaa(Ipv6Addr),
     /// Mail exchange.
     Mx(i16, String),
+    /// Authoritative nameserver.
+    Ns(String),
+    /// Canonical name of an alias.
+    Cname(String),
     /// Pointer to a domain name.
     Ptr(String),
     /// Responsible person.
     Rp(String, String),
-    /// Text.
-    Txt(Vec<String>),
+    /// Start of a zone of authority: primary nameserver, responsible mailbox, serial,
+    /// refresh interval, retry interval, expiry and minimum TTL.
+    Soa(String, String, u32,
+        #[cfg_attr(feature = "serde", serde(with = "seconds"))] Duration,
+        #[cfg_attr(feature = "serde", serde(with = "seconds"))] Duration,
+        #[cfg_attr(feature = "serde", serde(with = "seconds"))] Duration,
+        #[cfg_attr(feature = "serde", serde(with = "seconds"))] Duration),
+    /// Text. Each element is one character-string and may contain arbitrary bytes.
+    Txt(Vec<Vec<u8>>),
+    /// Delegation signer: key tag, algorithm, digest type and digest.
+    Ds(u16, u8, u8, Vec<u8>),
+    /// DNSSEC public key: flags, protocol, algorithm and public key.
+    Dnskey(u16, u8, u8, Vec<u8>),
+    /// Child copy of a delegation signer: key tag, algorithm, digest type and digest.
+    Cds(u16, u8, u8, Vec<u8>),
+    /// Child copy of a DNSSEC public key: flags, protocol, algorithm and public key.
+    Cdnskey(u16, u8, u8, Vec<u8>),
+    /// Child-to-parent synchronization: SOA serial, flags and the types to synchronize.
+    Csync(u32, u16, Vec<Type>),
+    /// Zone message digest: serial, scheme, hash algorithm and digest.
+    Zonemd(u32, u8, u8, Vec<u8>),
 }
 
 impl Data {
     /// Returns the type of the data.
     pub fn to_type(&self) -> Type {
         match *self {
-            Data::A(..)    => Type::A,
-            Data::Aaaa(..) => Type::Aaaa,
-            Data::Mx(..)   => Type::Mx,
-            Data::Ptr(..)  => Type::Ptr,
-            Data::Rp(..)   => Type::Rp,
-            Data::Txt(..)  => Type::Txt,
+            Data::A(..)       => Type::A,
+            Data::Aaaa(..)    => Type::Aaaa,
+            Data::Mx(..)      => Type::Mx,
+            Data::Ns(..)      => Type::Ns,
+            Data::Cname(..)   => Type::Cname,
+            Data::Ptr(..)     => Type::Ptr,
+            Data::Rp(..)      => Type::Rp,
+            Data::Soa(..)     => Type::Soa,
+            Data::Txt(..)     => Type::Txt,
+            Data::Ds(..)      => Type::Ds,
+            Data::Dnskey(..)  => Type::Dnskey,
+            Data::Cds(..)     => Type::Cds,
+            Data::Cdnskey(..) => Type::Cdnskey,
+            Data::Csync(..)   => Type::Csync,
+            Data::Zonemd(..)  => Type::Zonemd,
+        }
+    }
+
+    /// Returns the concatenation of all character-strings if this is a TXT record.
+    ///
+    /// This is the form in which SPF, DMARC and DKIM records are interpreted.
+    pub fn txt(&self) -> Option<Vec<u8>> {
+        match *self {
+            Data::Txt(ref strings) => {
+                let mut res = vec!();
+                for s in strings {
+                    res.extend(s.iter().cloned());
+                }
+                Some(res)
+            },
+            _ => None,
         }
     }
 }
@@ -1,38 +1,35 @@
-#![no_implicit_prelude]
+use core::net::{Ipv4Addr, Ipv6Addr};
+use core::{str};
+use core::time::{Duration};
 
-use std::net::{Ipv4Addr, Ipv6Addr};
-use std::{str};
-use std::vec::{Vec};
-use std::string::{String};
-use std::result::{Result};
-use std::result::Result::{Ok, Err};
-use std::io::{Read};
-use std::slice::{SliceExt};
-use std::time::{Duration};
+use alloc::vec::{Vec};
+use alloc::string::{String};
 
-use {Data, Packet, Record, Question, Class, Type, ResponseCode, QueryKind};
-use {A, AAAA, MX, PTR, RP, TXT, ALL, IN};
+use crate::{Data, Packet, Record, Question, Class, Type, ResponseCode, QueryKind, Edns};
+use crate::{A, AAAA, MX, NS, CNAME, PTR, RP, SOA, TXT, DS, DNSKEY, CDS, CDNSKEY, CSYNC, ZONEMD, ALL};
+use crate::{IN, CH, HS, NONE, OPT};
 
-use rust::{ReadExt2};
+use crate::rust::{ReadExt2};
 
 pub fn packet(src: &mut &[u8]) -> Result<Packet, ()> {
     let start = *src;
 
-    let id = trycvt!(src.read_i16_be());
-    let flags = trycvt!(src.read_u16_be());
+    let id = src.read_i16_be()?;
+    let flags = src.read_u16_be()?;
     let is_query = flags & 0b1000_0000_0000_0000 == 0;
     let kind_ = (flags & 0b0111_1000_0000_0000) >> 11;
-    let kind = trycvt!(kind(kind_));
+    let kind = kind(kind_)?;
     let is_authoritative = flags & 0b0000_0100_0000_0000 != 0;
     let truncated = flags & 0b0000_0010_0000_0000 != 0;
     let recursion_desired = flags & 0b0000_0001_0000_0000 != 0;
     let recursion_available = flags & 0b0000_0000_1000_0000 != 0;
+    let authentic_data = flags & 0b0000_0000_0010_0000 != 0;
     let response_code_ = flags & 0b0000_0000_0000_1111;
-    let response_code = trycvt!(response_code(response_code_));
-    let num_questions = trycvt!(src.read_u16_be());
-    let num_answers = trycvt!(src.read_u16_be());
-    let num_authority = trycvt!(src.read_u16_be());
-    let num_additional = trycvt!(src.read_u16_be());
+    let response_code = response_code(response_code_)?;
+    let num_questions = src.read_u16_be()?;
+    let num_answers = src.read_u16_be()?;
+    let num_authority = src.read_u16_be()?;
+    let num_additional = src.read_u16_be()?;
     let mut questions = vec!();
     for _ in 0..num_questions {
         match question(src, start) {
@@ -44,12 +41,20 @@ pub fn packet(src: &mut &[u8]) -> Result<Packet, ()> {
     let mut answers = vec!();
     let mut authority = vec!();
     let mut additional = vec!();
+    let mut edns = None;
     {
         let mut vec = [(&mut answers, num_answers),
                        (&mut authority, num_authority),
                        (&mut additional, num_additional)];
-        for v in vec.iter_mut() {
+        for (i, v) in vec.iter_mut().enumerate() {
             for _ in 0..v.1 {
+                // The OPT pseudo-record can only appear in the additional section.
+                if i == 2 {
+                    if let Some(e) = opt(src)? {
+                        edns = Some(e);
+                        continue;
+                    }
+                }
                 match record(src, start) {
                     Ok(q) => v.0.push(q),
                     Err(false) => return Err(()),
@@ -66,12 +71,15 @@ pub fn packet(src: &mut &[u8]) -> Result<Packet, ()> {
         truncated:           truncated,
         recursion_desired:   recursion_desired,
         recursion_available: recursion_available,
+        authentic_data:      authentic_data,
         response_code:       response_code,
 
         question:   questions,
         answer:     answers,
         authority:  authority,
         additional: additional,
+
+        edns: edns,
     })
 }
 
@@ -92,22 +100,60 @@ fn response_code(code: u16) -> Result<ResponseCode, ()> {
         3 => Ok(ResponseCode::NameError),
         4 => Ok(ResponseCode::NotImplemented),
         5 => Ok(ResponseCode::Refused),
+        6 => Ok(ResponseCode::NameExists),
+        7 => Ok(ResponseCode::RRsetExists),
+        8 => Ok(ResponseCode::RRsetMissing),
+        9 => Ok(ResponseCode::NotAuthoritative),
+        10 => Ok(ResponseCode::NotZone),
         _ => Err(()),
     }
 }
 
+/// Parses the OPT pseudo-record if `src` starts with one.
+pub fn opt(src: &mut &[u8]) -> Result<Option<Edns>, ()> {
+    // The owner of the OPT record is always the root domain.
+    if src.len() < 11 || src[0] != 0 || ((src[1] as u16) << 8 | src[2] as u16) != OPT {
+        return Ok(None);
+    }
+    *src = &src[3..];
+    let udp_payload_size = src.read_u16_be()?;
+    let extended_rcode = src.read_u8()?;
+    let version = src.read_u8()?;
+    let flags = src.read_u16_be()?;
+    let len = src.read_u16_be()? as usize;
+    if src.len() < len {
+        return Err(());
+    }
+    let mut rdata = &src[..len];
+    *src = &src[len..];
+    let mut options = vec!();
+    while !rdata.is_empty() {
+        let code = rdata.read_u16_be()?;
+        let len = rdata.read_u16_be()? as usize;
+        if rdata.len() < len {
+            return Err(());
+        }
+        options.push((code, rdata[..len].to_vec()));
+        rdata = &rdata[len..];
+    }
+    Ok(Some(Edns {
+        udp_payload_size: udp_payload_size,
+        extended_rcode:   extended_rcode,
+        version:          version,
+        dnssec_ok:        flags & 0b1000_0000_0000_0000 != 0,
+        options:          options,
+    }))
+}
+
 fn question(src: &mut &[u8], start: &[u8]) -> Result<Question, bool> {
     let name = trycvt!(domain_name(src, start));
-    let ty = ty(src);
-    let class = class(src);
-    if ty.is_err() || class.is_err() {
-        Err(true)
-    } else {
-        Ok(Question {
+    match (ty(src), class(src)) {
+        (Ok(ty), Ok(class)) => Ok(Question {
             name:  name,
-            ty:    ty.unwrap(),
-            class: class.unwrap(),
-        })
+            ty:    ty,
+            class: class,
+        }),
+        _ => Err(true),
     }
 }
 
@@ -115,52 +161,99 @@ fn record(src: &mut &[u8], start: &[u8]) -> Result<Record, bool> {
     let name = trycvt!(domain_name(src, start));
     let ty = ty(src);
     let class = class(src);
-    let ttl = trycvt!(src.read_i32_be());
+    let ttl = trycvt!(src.read_u32_be());
     let len = trycvt!(src.read_u16_be());
-    if ty.is_err() || class.is_err() {
-        return if len as usize <= src.len() {
+    let (ty, class) = match (ty, class) {
+        (Ok(ty), Ok(class)) => (ty, class),
+        _ if len as usize <= src.len() => {
             *src = &src[len as usize..];
-            Err(true)
-        } else {
-            Err(false)
-        };
-    }
-    let data = match ty.unwrap() {
-        Type::A    => trycvt!(a(src)),
-        Type::Aaaa => trycvt!(aaaa(src)),
-        Type::Mx   => trycvt!(mx(src, start)),
-        Type::Ptr  => trycvt!(ptr(src, start)),
-        Type::Rp   => trycvt!(rp(src, start)),
-        Type::Txt  => trycvt!(txt(src, len as usize)),
-        Type::All  => return Err(false),
+            return Err(true);
+        },
+        _ => return Err(false),
     };
+    let data = trycvt!(rdata(src, start, ty, len as usize));
+    // TTLs with the most significant bit set are treated as zero (RFC 2181 section 8).
+    let ttl = if ttl > i32::MAX as u32 { 0 } else { ttl };
     Ok(Record {
         name:         name,
-        class:        class.unwrap(),
-        time_to_live: Duration::seconds(ttl as i64),
+        class:        class,
+        time_to_live: Duration::from_secs(ttl as u64),
         data:         data,
     })
 }
 
+#[cfg(feature = "json")]
+/// Parses uncompressed record data of type `ty`.
+pub fn data(ty: Type, mut src: &[u8]) -> Result<Data, ()> {
+    let start = src;
+    let len = src.len();
+    let data = rdata(&mut src, start, ty, len)?;
+    match src.len() {
+        0 => Ok(data),
+        _ => Err(()),
+    }
+}
+
+fn rdata(src: &mut &[u8], start: &[u8], ty: Type, len: usize) -> Result<Data, ()> {
+    match ty {
+        Type::A       => a(src),
+        Type::Aaaa    => aaaa(src),
+        Type::Mx      => mx(src, start),
+        Type::Ns      => ns(src, start),
+        Type::Cname   => cname(src, start),
+        Type::Ptr     => ptr(src, start),
+        Type::Rp      => rp(src, start),
+        Type::Soa     => soa(src, start),
+        Type::Txt     => txt(src, len),
+        Type::Ds      => key_or_digest(src, len, Data::Ds),
+        Type::Dnskey  => key_or_digest(src, len, Data::Dnskey),
+        Type::Cds     => key_or_digest(src, len, Data::Cds),
+        Type::Cdnskey => key_or_digest(src, len, Data::Cdnskey),
+        Type::Csync   => csync(src, len),
+        Type::Zonemd  => zonemd(src, len),
+        Type::All     => Err(()),
+    }
+}
+
 fn ty(src: &mut &[u8]) -> Result<Type, ()> {
-    let ty = trycvt!(src.read_u16_be());
+    let ty = src.read_u16_be()?;
+    type_from_u16(ty)
+}
+
+pub fn type_from_u16(ty: u16) -> Result<Type, ()> {
     match ty {
-        A    => Ok(Type::A),
-        AAAA => Ok(Type::Aaaa),
-        MX   => Ok(Type::Mx),
-        PTR  => Ok(Type::Ptr),
-        RP   => Ok(Type::Rp),
-        TXT  => Ok(Type::Txt),
-        ALL  => Ok(Type::All),
+        A       => Ok(Type::A),
+        AAAA    => Ok(Type::Aaaa),
+        MX      => Ok(Type::Mx),
+        NS      => Ok(Type::Ns),
+        CNAME   => Ok(Type::Cname),
+        PTR     => Ok(Type::Ptr),
+        RP      => Ok(Type::Rp),
+        SOA     => Ok(Type::Soa),
+        TXT     => Ok(Type::Txt),
+        DS      => Ok(Type::Ds),
+        DNSKEY  => Ok(Type::Dnskey),
+        CDS     => Ok(Type::Cds),
+        CDNSKEY => Ok(Type::Cdnskey),
+        CSYNC   => Ok(Type::Csync),
+        ZONEMD  => Ok(Type::Zonemd),
+        ALL     => Ok(Type::All),
         _ => Err(())
     }
 }
 
 fn class(src: &mut &[u8]) -> Result<Class, ()> {
-    let ty = trycvt!(src.read_u16_be());
-    match ty {
-        IN  => Ok(Class::In),
-        ALL => Ok(Class::All),
+    let class = src.read_u16_be()?;
+    class_from_u16(class)
+}
+
+pub fn class_from_u16(class: u16) -> Result<Class, ()> {
+    match class {
+        IN   => Ok(Class::In),
+        CH   => Ok(Class::Ch),
+        HS   => Ok(Class::Hs),
+        NONE => Ok(Class::None),
+        ALL  => Ok(Class::All),
         _ => Err(())
     }
 }
@@ -170,7 +263,7 @@ fn a(src: &mut &[u8]) -> Result<Data, ()> {
         Err(())
     } else {
         let mut a = [0; 4];
-        let _ = src.read(&mut a);
+        let _ = src.read_exact(&mut a);
         Ok(Data::A(Ipv4Addr::new(a[0], a[1], a[2], a[3])))
     }
 }
@@ -192,27 +285,105 @@ fn aaaa(src: &mut &[u8]) -> Result<Data, ()> {
 }
 
 fn mx(src: &mut &[u8], start: &[u8]) -> Result<Data, ()> {
-    let preference = trycvt!(src.read_i16_be());
-    let domain = trycvt!(domain_name(src, start));
+    let preference = src.read_i16_be()?;
+    let domain = domain_name(src, start)?;
     Ok(Data::Mx(preference, domain))
 }
 
+fn ns(src: &mut &[u8], start: &[u8]) -> Result<Data, ()> {
+    let domain = domain_name(src, start)?;
+    Ok(Data::Ns(domain))
+}
+
+fn cname(src: &mut &[u8], start: &[u8]) -> Result<Data, ()> {
+    let domain = domain_name(src, start)?;
+    Ok(Data::Cname(domain))
+}
+
 fn ptr(src: &mut &[u8], start: &[u8]) -> Result<Data, ()> {
-    let domain = trycvt!(domain_name(src, start));
+    let domain = domain_name(src, start)?;
     Ok(Data::Ptr(domain))
 }
 
 fn rp(src: &mut &[u8], start: &[u8]) -> Result<Data, ()> {
-    let mbox = trycvt!(domain_name(src, start));
-    let txt = trycvt!(domain_name(src, start));
+    let mbox = domain_name(src, start)?;
+    let txt = domain_name(src, start)?;
     Ok(Data::Rp(mbox, txt))
 }
 
+fn soa(src: &mut &[u8], start: &[u8]) -> Result<Data, ()> {
+    let mname = domain_name(src, start)?;
+    let rname = domain_name(src, start)?;
+    let serial = src.read_u32_be()?;
+    let refresh = src.read_u32_be()?;
+    let retry = src.read_u32_be()?;
+    let expire = src.read_u32_be()?;
+    let minimum = src.read_u32_be()?;
+    Ok(Data::Soa(mname, rname, serial, Duration::from_secs(refresh as u64),
+                 Duration::from_secs(retry as u64), Duration::from_secs(expire as u64),
+                 Duration::from_secs(minimum as u64)))
+}
+
+/// Parses the common layout of DS, DNSKEY, CDS and CDNSKEY data: a 16 bit field, two 8
+/// bit fields and the remaining bytes.
+fn key_or_digest(src: &mut &[u8], total_len: usize,
+                 data: fn(u16, u8, u8, Vec<u8>) -> Data) -> Result<Data, ()> {
+    if total_len < 4 || src.len() < total_len {
+        return Err(());
+    }
+    let a = src.read_u16_be()?;
+    let b = src.read_u8()?;
+    let c = src.read_u8()?;
+    let rest = src[..total_len - 4].to_vec();
+    *src = &src[total_len - 4..];
+    Ok(data(a, b, c, rest))
+}
+
+fn csync(src: &mut &[u8], total_len: usize) -> Result<Data, ()> {
+    if total_len < 6 || src.len() < total_len {
+        return Err(());
+    }
+    let serial = src.read_u32_be()?;
+    let flags = src.read_u16_be()?;
+    let mut bitmap = &src[..total_len - 6];
+    *src = &src[total_len - 6..];
+    let mut types = vec!();
+    while !bitmap.is_empty() {
+        let window = bitmap.read_u8()? as u16;
+        let len = bitmap.read_u8()? as usize;
+        if len == 0 || len > 32 || bitmap.len() < len {
+            return Err(());
+        }
+        for i in 0..len * 8 {
+            if bitmap[i / 8] & (0x80 >> (i % 8)) != 0 {
+                // Types that are not known to this library are skipped.
+                if let Ok(ty) = type_from_u16(window << 8 | i as u16) {
+                    types.push(ty);
+                }
+            }
+        }
+        bitmap = &bitmap[len..];
+    }
+    Ok(Data::Csync(serial, flags, types))
+}
+
+fn zonemd(src: &mut &[u8], total_len: usize) -> Result<Data, ()> {
+    if total_len < 6 || src.len() < total_len {
+        return Err(());
+    }
+    let serial = src.read_u32_be()?;
+    let scheme = src.read_u8()?;
+    let hash = src.read_u8()?;
+    let digest = src[..total_len - 6].to_vec();
+    *src = &src[total_len - 6..];
+    Ok(Data::Zonemd(serial, scheme, hash, digest))
+}
+
 fn txt(src: &mut &[u8], total_len: usize) -> Result<Data, ()> {
     let mut res = vec!();
     let mut cur_len = 0;
     while cur_len < total_len {
-        let txt = trycvt!(character_string(src));
+        let txt = character_string(src)?;
         cur_len += txt.len() + 1;
         res.push(txt);
     }
@@ -222,21 +393,21 @@ fn txt(src: &mut &[u8], total_len: usize) -> Result<Data, ()> {
 fn domain_name(src: &mut &[u8], start: &[u8]) -> Result<String, ()> {
     let mut res = String::new();
     loop {
-        let len = trycvt!(src.read_u8());
+        let len = src.read_u8()?;
         if len == 0 {
             break;
-        } else if res.len() > 0 {
+        } else if !res.is_empty() {
             res.push('.');
         }
         if len & 0b1100_0000 != 0 {
             return if len & 0b1100_0000 == 0b1100_0000 {
-                let b2 = trycvt!(src.read_u8());
+                let b2 = src.read_u8()?;
                 let offset = ((len as usize & 0b0011_1111) << 8) | (b2 as usize);
                 if start.len() < offset {
                     Err(())
                 } else {
                     let mut tmp = &start[offset..];
-                    let s = trycvt!(domain_name(&mut tmp, start));
+                    let s = domain_name(&mut tmp, start)?;
                     res.push_str(&s);
                     Ok(res)
                 }
@@ -247,30 +418,44 @@ fn domain_name(src: &mut &[u8], start: &[u8]) -> Result<String, ()> {
         if src.len() < len as usize {
             return Err(());
         }
-        unsafe {
-            res.reserve(len as usize);
-            let oldlen = res.len();
-            res.as_mut_vec().set_len(oldlen + len as usize);
-            let _ = src.read(&mut res.as_mut_vec()[oldlen..]);
-            if str::from_utf8(&mut res.as_mut_vec()[oldlen..]).is_err() {
-                res.as_mut_vec().set_len(oldlen);
-                return Err(());
-            }
-        }
+        let label = trycvt!(str::from_utf8(&src[..len as usize]));
+        res.push_str(label);
+        *src = &src[len as usize..];
     }
     Ok(res)
 }
 
-fn character_string(src: &mut &[u8]) -> Result<String, ()> {
-    let len = trycvt!(src.read_u8());
+fn character_string(src: &mut &[u8]) -> Result<Vec<u8>, ()> {
+    let len = src.read_u8()?;
     if src.len() < len as usize {
         return Err(());
     }
-    let mut res = Vec::with_capacity(len as usize);
-    unsafe { res.set_len(len as usize); }
-    let _ = src.read(&mut res);
-    match String::from_utf8(res) {
-        Ok(s) => Ok(s),
-        _ => Err(()),
+    let res = src[..len as usize].to_vec();
+    *src = &src[len as usize..];
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_codes() {
+        let codes = [
+            ResponseCode::Ok, ResponseCode::FormatError, ResponseCode::ServerFailure,
+            ResponseCode::NameError, ResponseCode::NotImplemented, ResponseCode::Refused,
+            ResponseCode::NameExists, ResponseCode::RRsetExists, ResponseCode::RRsetMissing,
+            ResponseCode::NotAuthoritative, ResponseCode::NotZone,
+        ];
+        let mut buf = [0; 12];
+        for (i, &code) in codes.iter().enumerate() {
+            let mut packet = Packet::query(1);
+            packet.is_query = false;
+            packet.response_code = code;
+            assert_eq!(packet.format(&mut buf), Ok(12));
+            assert_eq!(buf[3] & 0b1111, i as u8);
+            assert_eq!(Packet::parse(&buf), Ok((12, packet)));
+        }
+        assert_eq!(response_code(11), Err(()));
     }
 }
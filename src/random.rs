@@ -0,0 +1,18 @@
+//! Random numbers from the kernel.
+
+use std::fs::{File};
+use std::io::{Read};
+
+/// Fills `buf` with cryptographically secure random bytes from `/dev/urandom`.
+pub fn fill(buf: &mut [u8]) -> Result<(), ()> {
+    let mut file = trycvt!(File::open("/dev/urandom"));
+    trycvt!(file.read_exact(buf));
+    Ok(())
+}
+
+/// Returns a random `u16`.
+pub fn u16() -> Result<u16, ()> {
+    let mut buf = [0; 2];
+    fill(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
@@ -0,0 +1,350 @@
+//! Resolver configuration from `resolv.conf`.
+//!
+//! Understands the same keywords and options as the GNU C library and applies the
+//! `LOCALDOMAIN` and `RES_OPTIONS` environment variables. See resolv.conf(5) for more
+//! details.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV6};
+use std::time::{Duration};
+use std::{env, fs};
+
+/// Maximum number of nameservers that are used.
+const MAX_NAMESERVERS: usize = 3;
+/// Maximum number of sortlist entries.
+const MAX_SORTLIST: usize = 10;
+/// Maximum value of the `ndots` option.
+const MAX_NDOTS: u8 = 15;
+/// Minimum value of the `timeout` option in seconds. Smaller values are raised to it
+/// like in the C library.
+const MIN_TIMEOUT: u64 = 1;
+/// Maximum value of the `timeout` option in seconds.
+const MAX_TIMEOUT: u64 = 30;
+/// Maximum value of the `attempts` option.
+const MAX_ATTEMPTS: u8 = 5;
+
+/// The configuration of a stub resolver.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ResolverConfig {
+    /// Nameservers in the order in which they appear. At most three are used.
+    pub nameservers:    Vec<SocketAddr>,
+    /// Domains that are appended to names with fewer than `ndots` dots.
+    pub search:         Vec<String>,
+    /// Networks with their netmasks. Addresses are preferred in the order of the first
+    /// network that contains them.
+    pub sortlist:       Vec<(Ipv4Addr, Ipv4Addr)>,
+    /// Number of dots a name must contain to be tried as an absolute name first.
+    pub ndots:          u8,
+    /// Time to wait for a response from a nameserver.
+    pub timeout:        Duration,
+    /// Number of times all nameservers are tried.
+    pub attempts:       u8,
+    /// Set if queries are distributed over the nameservers instead of always trying the
+    /// first nameserver first.
+    pub rotate:         bool,
+    /// Set if queries use EDNS (RFC 6891).
+    pub edns0:          bool,
+    /// Set if the A and AAAA queries of an address lookup are sent one after the other
+    /// instead of in parallel.
+    pub single_request: bool,
+    /// Set if the nameservers are trusted to validate DNSSEC. Queries request the AD bit
+    /// and it is only kept in responses if this is set.
+    pub trust_ad:       bool,
+    /// Set if queries are sent over TCP instead of UDP.
+    pub use_vc:         bool,
+    /// Set if the letters of query names are randomly upper- or lower-cased and
+    /// responses must repeat the name exactly (draft-vixie-dnsext-dns0x20). Set by the
+    /// option `randomize-case`, which the C library ignores.
+    pub randomize_case: bool,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> ResolverConfig {
+        ResolverConfig {
+            nameservers:    vec!(),
+            search:         vec!(),
+            sortlist:       vec!(),
+            ndots:          1,
+            timeout:        Duration::from_secs(5),
+            attempts:       2,
+            rotate:         false,
+            edns0:          false,
+            single_request: false,
+            trust_ad:       false,
+            use_vc:         false,
+            randomize_case: false,
+        }
+    }
+}
+
+impl ResolverConfig {
+    /// Parses the contents of a `resolv.conf` file.
+    ///
+    /// Lines that cannot be parsed and unknown options are ignored. If there are several
+    /// `domain` and `search` lines, the last one wins.
+    pub fn parse(src: &str) -> ResolverConfig {
+        let mut conf = ResolverConfig::default();
+        for line in src.lines() {
+            if line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(k) => k,
+                _ => continue,
+            };
+            match keyword {
+                "nameserver" if conf.nameservers.len() < MAX_NAMESERVERS => {
+                    if let Some(addr) = words.next().and_then(nameserver) {
+                        conf.nameservers.push(addr);
+                    }
+                },
+                "domain" => {
+                    if let Some(domain) = words.next() {
+                        conf.search = vec!(domain.to_string());
+                    }
+                },
+                "search" => {
+                    conf.search = words.map(|w| w.to_string()).collect();
+                },
+                "sortlist" => {
+                    conf.sortlist = words.filter_map(sortlist_entry).take(MAX_SORTLIST)
+                                         .collect();
+                },
+                "options" => {
+                    for option in words {
+                        conf.apply_option(option);
+                    }
+                },
+                _ => { },
+            }
+        }
+        conf
+    }
+
+    /// Reads the configuration from a file and applies the environment.
+    ///
+    /// If the file contains neither a `domain` nor a `search` line, the search list
+    /// consists of the domain of the hostname. Afterwards `LOCALDOMAIN` replaces the
+    /// search list and the options in `RES_OPTIONS` are applied.
+    ///
+    /// Returns an error if the file cannot be read.
+    pub fn read(path: &str) -> Result<ResolverConfig, ()> {
+        let mut conf = ResolverConfig::parse(&trycvt!(fs::read_to_string(path)));
+        if conf.search.is_empty() {
+            if let Some(domain) = hostname_domain() {
+                conf.search.push(domain);
+            }
+        }
+        conf.apply_env();
+        Ok(conf)
+    }
+
+    /// Reads the configuration of the system from `/etc/resolv.conf`.
+    pub fn system() -> Result<ResolverConfig, ()> {
+        ResolverConfig::read("/etc/resolv.conf")
+    }
+
+    /// Returns the nameservers that are queried. Like the C library, the loopback address
+    /// is used if no nameserver is configured.
+    pub fn servers(&self) -> Vec<SocketAddr> {
+        if self.nameservers.is_empty() {
+            vec!(SocketAddr::from((Ipv4Addr::LOCALHOST, 53)))
+        } else {
+            self.nameservers.clone()
+        }
+    }
+
+    /// Returns the names that are tried, in order, when `name` is looked up.
+    ///
+    /// A name with a trailing dot is fully qualified and only tried as it is. Otherwise
+    /// the name is qualified with each domain of the search list. If the name contains
+    /// at least `ndots` dots, it is tried as it is before the search list, otherwise
+    /// after it. Trailing dots are removed from the returned names.
+    pub fn candidates(&self, name: &str) -> Vec<String> {
+        if let Some(name) = name.strip_suffix('.') {
+            return vec!(name.to_string());
+        }
+        let mut res: Vec<String> = self.search.iter().map(|domain| {
+            format!("{}.{}", name, domain.trim_end_matches('.'))
+        }).collect();
+        let dots = name.bytes().filter(|&b| b == b'.').count();
+        if dots >= self.ndots as usize {
+            res.insert(0, name.to_string());
+        } else {
+            res.push(name.to_string());
+        }
+        res
+    }
+
+    /// Sorts addresses by the sortlist.
+    ///
+    /// Addresses in an earlier network come first and addresses in none of the networks,
+    /// including all IPv6 addresses, come last. The sort is stable.
+    pub fn apply_sortlist(&self, ips: &mut [IpAddr]) {
+        ips.sort_by_key(|ip| {
+            let pos = match *ip {
+                IpAddr::V4(ip) => self.sortlist.iter().position(|&(net, mask)| {
+                    u32::from(ip) & u32::from(mask) == u32::from(net) & u32::from(mask)
+                }),
+                IpAddr::V6(..) => None,
+            };
+            pos.unwrap_or(self.sortlist.len())
+        });
+    }
+
+    /// Applies the `LOCALDOMAIN` and `RES_OPTIONS` environment variables.
+    pub fn apply_env(&mut self) {
+        if let Ok(domains) = env::var("LOCALDOMAIN") {
+            self.search = domains.split_whitespace().map(|w| w.to_string()).collect();
+        }
+        if let Ok(options) = env::var("RES_OPTIONS") {
+            self.apply_options(&options);
+        }
+    }
+
+    /// Applies a whitespace-separated list of options as found after `options`.
+    pub fn apply_options(&mut self, options: &str) {
+        for option in options.split_whitespace() {
+            self.apply_option(option);
+        }
+    }
+
+    fn apply_option(&mut self, option: &str) {
+        let (name, value) = match option.find(':') {
+            Some(pos) => (&option[..pos], Some(&option[pos+1..])),
+            _ => (option, None),
+        };
+        let value = value.and_then(|v| v.parse::<u64>().ok());
+        match (name, value) {
+            ("ndots", Some(n))    => self.ndots = n.min(MAX_NDOTS as u64) as u8,
+            ("timeout", Some(n))  => {
+                self.timeout = Duration::from_secs(n.clamp(MIN_TIMEOUT, MAX_TIMEOUT));
+            },
+            ("attempts", Some(n)) => self.attempts = n.min(MAX_ATTEMPTS as u64) as u8,
+            ("rotate", _)         => self.rotate = true,
+            ("edns0", _)          => self.edns0 = true,
+            ("single-request", _) => self.single_request = true,
+            ("trust-ad", _)       => self.trust_ad = true,
+            ("use-vc", _)         => self.use_vc = true,
+            ("randomize-case", _) => self.randomize_case = true,
+            _ => { },
+        }
+    }
+}
+
+/// Parses the address of a nameserver. IPv6 addresses can have a scope ID that is
+/// either numeric or the name of an interface.
+fn nameserver(s: &str) -> Option<SocketAddr> {
+    let (addr, scope) = match s.find('%') {
+        Some(pos) => (&s[..pos], Some(&s[pos+1..])),
+        _ => (s, None),
+    };
+    match (addr.parse().ok()?, scope) {
+        (IpAddr::V4(addr), None) => Some(SocketAddr::from((addr, 53))),
+        (IpAddr::V6(addr), scope) => {
+            let scope = scope.map(scope_id).unwrap_or(0);
+            Some(SocketAddr::V6(SocketAddrV6::new(addr, 53, 0, scope)))
+        },
+        _ => None,
+    }
+}
+
+fn scope_id(s: &str) -> u32 {
+    if let Ok(id) = s.parse() {
+        return id;
+    }
+    if s.is_empty() || s.contains('/') || s.starts_with('.') {
+        return 0;
+    }
+    let path = format!("/sys/class/net/{}/ifindex", s);
+    fs::read_to_string(path).ok().and_then(|i| i.trim().parse().ok()).unwrap_or(0)
+}
+
+/// Parses a sortlist entry `address[/netmask]`. Without a netmask, the netmask of the
+/// address class is used.
+fn sortlist_entry(s: &str) -> Option<(Ipv4Addr, Ipv4Addr)> {
+    let (addr, mask) = match s.find(['/', '&']) {
+        Some(pos) => (&s[..pos], Some(&s[pos+1..])),
+        _ => (s, None),
+    };
+    let addr: Ipv4Addr = addr.parse().ok()?;
+    let mask = match mask {
+        Some(mask) => mask.parse().ok()?,
+        _ => match addr.octets()[0] {
+            0..=127   => Ipv4Addr::new(255, 0, 0, 0),
+            128..=191 => Ipv4Addr::new(255, 255, 0, 0),
+            _         => Ipv4Addr::new(255, 255, 255, 0),
+        },
+    };
+    Some((addr, mask))
+}
+
+/// Returns the domain part of the hostname.
+fn hostname_domain() -> Option<String> {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname").ok()?;
+    let name = name.trim();
+    let pos = name.find('.')?;
+    let domain = &name[pos+1..];
+    if domain.is_empty() {
+        None
+    } else {
+        Some(domain.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let conf = ResolverConfig::parse("\
+            # comment\n\
+            nameserver 192.0.2.1\n\
+            nameserver fe80::1%2\n\
+            nameserver invalid\n\
+            nameserver 192.0.2.2\n\
+            nameserver 192.0.2.3\n\
+            domain example.org\n\
+            search example.com example.net\n\
+            sortlist 130.155.160.0/255.255.240.0 130.155.0.0\n\
+            options ndots:20 timeout:0 attempts:3 rotate edns0 trust-ad unknown\n");
+        assert_eq!(conf.nameservers, vec!(
+            "192.0.2.1:53".parse().unwrap(),
+            SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 53, 0, 2)),
+            "192.0.2.2:53".parse().unwrap(),
+        ));
+        assert_eq!(conf.search, vec!("example.com", "example.net"));
+        assert_eq!(conf.sortlist, vec!(
+            (Ipv4Addr::new(130, 155, 160, 0), Ipv4Addr::new(255, 255, 240, 0)),
+            (Ipv4Addr::new(130, 155, 0, 0), Ipv4Addr::new(255, 255, 0, 0)),
+        ));
+        assert_eq!(conf.ndots, MAX_NDOTS);
+        assert_eq!(conf.timeout, Duration::from_secs(MIN_TIMEOUT));
+        assert_eq!(conf.attempts, 3);
+        assert!(conf.rotate && conf.edns0 && conf.trust_ad);
+        assert!(!conf.use_vc && !conf.single_request && !conf.randomize_case);
+    }
+
+    #[test]
+    fn candidates() {
+        let mut conf = ResolverConfig::parse("search example.com example.net.\n");
+        assert_eq!(conf.candidates("www"), vec!("www.example.com", "www.example.net", "www"));
+        assert_eq!(conf.candidates("www.a"), vec!("www.a", "www.a.example.com",
+                                                  "www.a.example.net"));
+        assert_eq!(conf.candidates("www."), vec!("www"));
+        conf.apply_options("ndots:2");
+        assert_eq!(conf.candidates("www.a")[0], "www.a.example.com");
+    }
+
+    #[test]
+    fn sortlist() {
+        let conf = ResolverConfig::parse("sortlist 10.0.0.0 192.168.1.0/255.255.255.0\n");
+        let mut ips: Vec<IpAddr> = ["2001:db8::1", "192.0.2.1", "192.168.1.5", "10.2.3.4"]
+            .iter().map(|s| s.parse().unwrap()).collect();
+        conf.apply_sortlist(&mut ips);
+        let expected: Vec<IpAddr> = ["10.2.3.4", "192.168.1.5", "2001:db8::1", "192.0.2.1"]
+            .iter().map(|s| s.parse().unwrap()).collect();
+        assert_eq!(ips, expected);
+    }
+}
@@ -0,0 +1,197 @@
+//! Names used for reverse lookups (RFC 1035 section 3.5, RFC 3596 section 2.5).
+//!
+//! The PTR records of an IPv4 address are stored under the octets of the address in
+//! reverse order below `in-addr.arpa`, those of an IPv6 address under the nibbles of the
+//! address in reverse order below `ip6.arpa`.
+//!
+//! Networks smaller than a /24 can be delegated with the classless scheme of RFC 2317.
+//! The names in such a zone contain an additional label that describes the network,
+//! e.g., `1.0/25.2.0.192.in-addr.arpa`.
+
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use alloc::vec::{Vec};
+use alloc::string::{String};
+
+/// Returns the reverse lookup name of an address.
+///
+/// E.g., `1.2.0.192.in-addr.arpa` for `192.0.2.1`.
+pub fn name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let o = ip.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+        },
+        IpAddr::V6(ip) => {
+            let mut res = String::with_capacity(72);
+            for b in ip.octets().iter().rev() {
+                res.push_str(&format!("{:x}.{:x}.", b & 0xf, b >> 4));
+            }
+            res.push_str("ip6.arpa");
+            res
+        },
+    }
+}
+
+/// Returns the name of the classless zone (RFC 2317) of the network with the prefix
+/// length `prefix` that contains `ip`.
+///
+/// E.g., `0/25.2.0.192.in-addr.arpa` for `192.0.2.1` and a prefix length of 25.
+///
+/// The prefix length must be between 25 and 31.
+pub fn classless_zone(ip: Ipv4Addr, prefix: u8) -> Result<String, ()> {
+    if !(25..=31).contains(&prefix) {
+        return Err(());
+    }
+    let o = ip.octets();
+    let net = o[3] & (0xff << (32 - prefix));
+    Ok(format!("{}/{}.{}.{}.{}.in-addr.arpa", net, prefix, o[2], o[1], o[0]))
+}
+
+/// Returns the reverse lookup name of an address in the classless zone (RFC 2317) of
+/// the network with the prefix length `prefix`.
+///
+/// E.g., `1.0/25.2.0.192.in-addr.arpa` for `192.0.2.1` and a prefix length of 25.
+///
+/// The prefix length must be between 25 and 31.
+pub fn classless_name(ip: Ipv4Addr, prefix: u8) -> Result<String, ()> {
+    let zone = classless_zone(ip, prefix)?;
+    Ok(format!("{}.{}", ip.octets()[3], zone))
+}
+
+/// Returns the address whose reverse lookup name is `name`.
+///
+/// Accepts names below `in-addr.arpa` with four octets, names below `ip6.arpa` with 32
+/// nibbles, and names in classless zones. The network label of a classless name can be
+/// written as `net/prefix` or as the range `first-last`. Case and a trailing dot are
+/// ignored.
+pub fn address(name: &str) -> Result<IpAddr, ()> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    let labels: Vec<&str> = name.split('.').collect();
+    let n = labels.len();
+    if n < 2 {
+        return Err(());
+    }
+    let (rest, zone) = labels.split_at(n - 2);
+    if zone[1].eq_ignore_ascii_case("arpa") {
+        if zone[0].eq_ignore_ascii_case("in-addr") {
+            return v4(rest).map(IpAddr::V4);
+        }
+        if zone[0].eq_ignore_ascii_case("ip6") {
+            return v6(rest).map(IpAddr::V6);
+        }
+    }
+    Err(())
+}
+
+fn v4(labels: &[&str]) -> Result<Ipv4Addr, ()> {
+    let (host, octets) = match labels.len() {
+        4 => (labels[0], &labels[1..]),
+        5 => {
+            let host = octet(labels[0])?;
+            if !in_classless_net(host, labels[1])? {
+                return Err(());
+            }
+            (labels[0], &labels[2..])
+        },
+        _ => return Err(()),
+    };
+    Ok(Ipv4Addr::new(octet(octets[2])?, octet(octets[1])?, octet(octets[0])?, octet(host)?))
+}
+
+/// Returns whether the last octet `host` is part of the network described by the
+/// network label of a classless name.
+fn in_classless_net(host: u8, label: &str) -> Result<bool, ()> {
+    if let Some((net, prefix)) = label.split_once('/') {
+        let net = octet(net)?;
+        let prefix = trycvt!(prefix.parse::<u8>());
+        if !(25..=31).contains(&prefix) {
+            return Err(());
+        }
+        let mask = 0xff << (32 - prefix);
+        return Ok(host & mask == net);
+    }
+    if let Some((first, last)) = label.split_once('-') {
+        return Ok(octet(first)? <= host && host <= octet(last)?);
+    }
+    Err(())
+}
+
+fn v6(labels: &[&str]) -> Result<Ipv6Addr, ()> {
+    if labels.len() != 32 {
+        return Err(());
+    }
+    let mut octets = [0; 16];
+    for (i, pair) in labels.chunks(2).enumerate() {
+        octets[15 - i] = nibble(pair[1])? << 4 | nibble(pair[0])?;
+    }
+    Ok(Ipv6Addr::from(octets))
+}
+
+fn octet(label: &str) -> Result<u8, ()> {
+    // Reject leading zeros and signs which `parse` would accept.
+    if label.is_empty() || label.len() > 3 || !label.bytes().all(|b| b.is_ascii_digit())
+            || (label.len() > 1 && label.starts_with('0')) {
+        return Err(());
+    }
+    Ok(trycvt!(label.parse()))
+}
+
+fn nibble(label: &str) -> Result<u8, ()> {
+    match label.as_bytes() {
+        &[b] if b.is_ascii_hexdigit() => Ok((b as char).to_digit(16).unwrap() as u8),
+        _ => Err(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv4() {
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        assert_eq!(name(ip), "1.2.0.192.in-addr.arpa");
+        assert_eq!(address("1.2.0.192.in-addr.arpa"), Ok(ip));
+        assert_eq!(address("1.2.0.192.IN-ADDR.ARPA."), Ok(ip));
+        assert_eq!(address("2.0.192.in-addr.arpa"), Err(()));
+        assert_eq!(address("01.2.0.192.in-addr.arpa"), Err(()));
+        assert_eq!(address("256.2.0.192.in-addr.arpa"), Err(()));
+        assert_eq!(address("+1.2.0.192.in-addr.arpa"), Err(()));
+        assert_eq!(address("1.2.0.192.example.com"), Err(()));
+    }
+
+    #[test]
+    fn ipv6() {
+        let ip: IpAddr = "2001:db8::567:89ab".parse().unwrap();
+        let rev = "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa";
+        assert_eq!(name(ip), rev);
+        assert_eq!(address(rev), Ok(ip));
+        assert_eq!(address(&rev.to_uppercase()), Ok(ip));
+        assert_eq!(address(&rev[2..]), Err(()));
+        assert_eq!(address(&format!("0.{}", rev)), Err(()));
+        assert_eq!(address(&rev.replacen('b', "g", 1)), Err(()));
+        assert_eq!(address(&rev.replacen('b', "bb", 1)), Err(()));
+    }
+
+    /// Names in classless zones (RFC 2317).
+    #[test]
+    fn classless() {
+        let ip = Ipv4Addr::new(192, 0, 2, 130);
+        assert_eq!(classless_zone(ip, 25).unwrap(), "128/25.2.0.192.in-addr.arpa");
+        assert_eq!(classless_name(ip, 25).unwrap(), "130.128/25.2.0.192.in-addr.arpa");
+        assert_eq!(classless_name(ip, 26).unwrap(), "130.128/26.2.0.192.in-addr.arpa");
+        assert_eq!(classless_name(ip, 31).unwrap(), "130.130/31.2.0.192.in-addr.arpa");
+        assert_eq!(classless_zone(ip, 24), Err(()));
+        assert_eq!(classless_zone(ip, 32), Err(()));
+        for prefix in 25..=31 {
+            let name = classless_name(ip, prefix).unwrap();
+            assert_eq!(address(&name), Ok(IpAddr::V4(ip)));
+        }
+        assert_eq!(address("130.128-191.2.0.192.in-addr.arpa"), Ok(IpAddr::V4(ip)));
+        // The address must be part of the network.
+        assert_eq!(address("1.128/25.2.0.192.in-addr.arpa"), Err(()));
+        assert_eq!(address("130.0-127.2.0.192.in-addr.arpa"), Err(()));
+        assert_eq!(address("130.128/24.2.0.192.in-addr.arpa"), Err(()));
+    }
+}
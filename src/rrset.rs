@@ -0,0 +1,107 @@
+use core::cmp::{Ordering};
+use core::time::{Duration};
+
+use alloc::vec::{Vec};
+use alloc::string::{String};
+
+#[cfg(feature = "serde")] use serde::{Serialize, Deserialize};
+
+use crate::{Class, Data, Record, Type};
+use crate::canonical;
+
+/// A set of records with the same owner, type and class.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RRset {
+    /// Domain name.
+    pub name:         String,
+    /// Class.
+    pub class:        Class,
+    /// Type of all records in the set.
+    pub ty:           Type,
+    /// Time to live of all records in the set.
+    #[cfg_attr(feature = "serde", serde(with = "crate::seconds"))]
+    pub time_to_live: Duration,
+    /// Data of the records. Never contains duplicates.
+    pub data:         Vec<Data>,
+}
+
+impl RRset {
+    /// Groups records into RRsets.
+    ///
+    /// Owner names are compared without regard to case. The RRsets are returned in the
+    /// order in which their first record appears and the data of each RRset in the order
+    /// of the records. Duplicate records are removed.
+    ///
+    /// All records of an RRset must have the same TTL (RFC 2181 section 5.2). If they
+    /// differ, the lowest TTL is used for the whole RRset.
+    pub fn group(records: &[Record]) -> Vec<RRset> {
+        let mut res: Vec<RRset> = vec!();
+        for r in records {
+            let ty = r.data.to_type();
+            let pos = res.iter().position(|s| {
+                s.ty == ty && s.class == r.class && canonical::names_equal(&s.name, &r.name)
+            });
+            match pos {
+                Some(i) => res[i].push(r.time_to_live, &r.data),
+                None => res.push(RRset {
+                    name:         r.name.clone(),
+                    class:        r.class,
+                    ty:           ty,
+                    time_to_live: r.time_to_live,
+                    data:         vec!(r.data.clone()),
+                }),
+            }
+        }
+        res
+    }
+
+    fn push(&mut self, ttl: Duration, data: &Data) {
+        if ttl < self.time_to_live {
+            self.time_to_live = ttl;
+        }
+        let wire = canonical::data(data);
+        if !self.data.iter().any(|d| canonical::data(d) == wire) {
+            self.data.push(data.clone());
+        }
+    }
+
+    /// Returns the records of the RRset.
+    pub fn records(&self) -> Vec<Record> {
+        self.data.iter().map(|d| Record {
+            name:         self.name.clone(),
+            class:        self.class,
+            time_to_live: self.time_to_live,
+            data:         d.clone(),
+        }).collect()
+    }
+
+    /// Sorts the data in canonical order (RFC 4034 section 6.3).
+    pub fn sort(&mut self) {
+        let mut keyed: Vec<_> = self.data.drain(..).map(|d| (canonical::data(&d), d)).collect();
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        self.data = keyed.into_iter().map(|k| k.1).collect();
+    }
+
+    /// Compares the owner, type and class of two RRsets in canonical order (RFC 4034
+    /// section 6.1).
+    pub fn cmp_canonical(&self, other: &RRset) -> Ordering {
+        canonical::compare_names(&self.name, &other.name)
+            .then((self.ty as u16).cmp(&(other.ty as u16)))
+            .then((self.class as u16).cmp(&(other.class as u16)))
+    }
+
+    /// Returns the canonical wire form of the RRset: the canonical form of each record in
+    /// canonical order with lower-case names and without compression.
+    ///
+    /// This is the form over which signatures are computed.
+    pub fn canonical(&self) -> Vec<u8> {
+        let mut sorted = self.clone();
+        sorted.sort();
+        let mut res = vec!();
+        for r in sorted.records() {
+            res.extend(canonical::record(&r));
+        }
+        res
+    }
+}
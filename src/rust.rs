@@ -1,68 +1,86 @@
-use std::io::{self, Write, Read};
+/// Big-endian writes into a byte slice. Each write advances the slice past the written
+/// bytes and fails without writing anything if the slice is too short.
+pub trait WriteExt2 {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), ()>;
 
-pub trait WriteExt2: Write {
-    fn write_u32_be(&mut self, val: u32) -> io::Result<()> {
+    fn write_u32_be(&mut self, val: u32) -> Result<(), ()> {
         let buf = [(val >> 24) as u8, (val >> 16) as u8, (val >> 8) as u8, val as u8];
         self.write_all(&buf)
     }
 
-    fn write_i32_be(&mut self, val: i32) -> io::Result<()> {
-        self.write_u32_be(val as u32)
-    }
-
-    fn write_u16_be(&mut self, val: u16) -> io::Result<()> {
+    fn write_u16_be(&mut self, val: u16) -> Result<(), ()> {
         let buf = [(val >> 8) as u8, val as u8];
         self.write_all(&buf)
     }
 
-    fn write_i16_be(&mut self, val: i16) -> io::Result<()> {
+    fn write_i16_be(&mut self, val: i16) -> Result<(), ()> {
         self.write_u16_be(val as u16)
     }
 
-    fn write_u8(&mut self, val: u8) -> io::Result<()> {
+    fn write_u8(&mut self, val: u8) -> Result<(), ()> {
         self.write_all(&[val])
     }
 }
 
-impl<T: Write> WriteExt2 for T { }
+impl WriteExt2 for &mut [u8] {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), ()> {
+        if self.len() < buf.len() {
+            return Err(());
+        }
+        let tmp = core::mem::take(self);
+        let (dst, rest) = tmp.split_at_mut(buf.len());
+        dst.copy_from_slice(buf);
+        *self = rest;
+        Ok(())
+    }
+}
+
+/// Big-endian reads from a byte slice. Each read advances the slice past the read bytes
+/// and fails without consuming anything if the slice is too short.
+pub trait ReadExt2 {
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ()>;
 
-pub trait ReadExt2: Read {
-    fn read_u32_be(&mut self) -> io::Result<u32> {
+    fn read_u32_be(&mut self) -> Result<u32, ()> {
         let mut buf = [0; 4];
-        try!(self.read(&mut buf));
+        self.read_exact(&mut buf)?;
         Ok((buf[0] as u32) << 24 | (buf[1] as u32) << 16 | (buf[2] as u32) << 8
             | buf[3] as u32)
     }
 
-    fn read_i32_be(&mut self) -> io::Result<i32> {
-        self.read_u32_be().map(|v| v as i32)
-    }
-
-    fn read_u16_be(&mut self) -> io::Result<u16> {
+    fn read_u16_be(&mut self) -> Result<u16, ()> {
         let mut buf = [0; 2];
-        try!(self.read(&mut buf));
+        self.read_exact(&mut buf)?;
         Ok((buf[0] as u16) << 8 | buf[1] as u16)
     }
 
-    fn read_i16_be(&mut self) -> io::Result<i16> {
+    fn read_i16_be(&mut self) -> Result<i16, ()> {
         self.read_u16_be().map(|v| v as i16)
     }
 
-    fn read_u8(&mut self) -> io::Result<u8> {
+    fn read_u8(&mut self) -> Result<u8, ()> {
         let mut buf = [0];
-        try!(self.read(&mut buf));
+        self.read_exact(&mut buf)?;
         Ok(buf[0])
     }
 }
 
-impl<T: Read> ReadExt2 for T { }
+impl ReadExt2 for &[u8] {
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ()> {
+        if self.len() < buf.len() {
+            return Err(());
+        }
+        buf.copy_from_slice(&self[..buf.len()]);
+        *self = &self[buf.len()..];
+        Ok(())
+    }
+}
 
 #[macro_export]
 macro_rules! trycvt {
     ($e:expr) => {
         match $e {
             Ok(v) => v,
-            Err(_) => return Err(::std::default::Default::default()),
+            Err(_) => return Err(::core::default::Default::default()),
         }
     }
 }
@@ -0,0 +1,15 @@
+//! Serializes durations as a whole number of seconds, the way TTLs and SOA timers are
+//! written in presentation form.
+
+use core::time::{Duration};
+
+use serde::{Serializer, Deserializer, Deserialize};
+
+pub fn serialize<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u32(d.as_secs() as u32)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    let secs = u32::deserialize(d)?;
+    Ok(Duration::from_secs(secs as u64))
+}
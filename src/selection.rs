@@ -0,0 +1,217 @@
+//! Destination address selection (RFC 6724).
+//!
+//! Orders the addresses of a host so that the addresses that are most likely to work
+//! come first. The order depends on the source address that would be used for each
+//! destination and on a policy table that assigns precedences and labels to prefixes.
+
+use core::cmp::{Ordering};
+use core::net::{IpAddr, Ipv6Addr};
+
+use alloc::vec::{Vec};
+
+/// A prefix of the policy table with its precedence or label.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PolicyEntry {
+    /// The prefix. IPv4 addresses are matched as IPv4-mapped IPv6 addresses.
+    pub prefix: Ipv6Addr,
+    /// Length of the prefix in bits.
+    pub len:    u8,
+    /// Precedence or label of the addresses with this prefix.
+    pub value:  u32,
+}
+
+/// A policy table.
+///
+/// The entry with the longest matching prefix applies to an address. Addresses without
+/// a matching entry have precedence and label 0.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Policy {
+    /// Precedences. Addresses with a higher precedence are preferred.
+    pub precedence: Vec<PolicyEntry>,
+    /// Labels. Destinations with the same label as their source are preferred.
+    pub label:      Vec<PolicyEntry>,
+}
+
+/// The default policy table of RFC 6724 section 2.1 as prefix, length, precedence and
+/// label.
+const DEFAULT_POLICY: [(Ipv6Addr, u8, u32, u32); 9] = [
+    (Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1),      128, 50,  0),
+    (Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0),        0, 40,  1),
+    (Ipv6Addr::new(0, 0, 0, 0, 0, 0xffff, 0, 0),  96, 35,  4),
+    (Ipv6Addr::new(0x2002, 0, 0, 0, 0, 0, 0, 0),  16, 30,  2),
+    (Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 0),  32,  5,  5),
+    (Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0),   7,  3, 13),
+    (Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0),       96,  1,  3),
+    (Ipv6Addr::new(0xfec0, 0, 0, 0, 0, 0, 0, 0),  10,  1, 11),
+    (Ipv6Addr::new(0x3ffe, 0, 0, 0, 0, 0, 0, 0),  16,  1, 12),
+];
+
+impl Default for Policy {
+    fn default() -> Policy {
+        let entry = |prefix, len, value| PolicyEntry {
+            prefix: prefix,
+            len:    len,
+            value:  value,
+        };
+        Policy {
+            precedence: DEFAULT_POLICY.iter().map(|p| entry(p.0, p.1, p.2)).collect(),
+            label:      DEFAULT_POLICY.iter().map(|p| entry(p.0, p.1, p.3)).collect(),
+        }
+    }
+}
+
+impl Policy {
+    /// Parses the `precedence` and `label` lines of a gai.conf file.
+    ///
+    /// Each line has the form `precedence prefix/len value` or `label prefix/len value`.
+    /// If there is at least one `precedence` line, the lines replace the default
+    /// precedences. The same holds for labels. Other lines are ignored.
+    pub fn parse_gai_conf(src: &str) -> Policy {
+        let mut precedence = vec!();
+        let mut label = vec!();
+        for line in src.lines() {
+            let line = match line.find('#') {
+                Some(pos) => &line[..pos],
+                _ => line,
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.len() != 3 {
+                continue;
+            }
+            let entry = match policy_entry(words[1], words[2]) {
+                Some(e) => e,
+                _ => continue,
+            };
+            match words[0] {
+                "precedence" => precedence.push(entry),
+                "label"      => label.push(entry),
+                _ => { },
+            }
+        }
+        let mut policy = Policy::default();
+        if !precedence.is_empty() {
+            policy.precedence = precedence;
+        }
+        if !label.is_empty() {
+            policy.label = label;
+        }
+        policy
+    }
+
+    fn precedence(&self, ip: IpAddr) -> u32 {
+        lookup(&self.precedence, ip)
+    }
+
+    fn label(&self, ip: IpAddr) -> u32 {
+        lookup(&self.label, ip)
+    }
+}
+
+fn policy_entry(prefix: &str, value: &str) -> Option<PolicyEntry> {
+    let (addr, len) = prefix.split_once('/')?;
+    let len: u8 = len.parse().ok()?;
+    if len > 128 {
+        return None;
+    }
+    Some(PolicyEntry {
+        prefix: addr.parse().ok()?,
+        len:    len,
+        value:  value.parse().ok()?,
+    })
+}
+
+fn lookup(table: &[PolicyEntry], ip: IpAddr) -> u32 {
+    let ip = to_v6(ip);
+    let entry = table.iter().filter(|e| common_prefix_len(&e.prefix, &ip) >= e.len)
+                     .max_by_key(|e| e.len);
+    entry.map(|e| e.value).unwrap_or(0)
+}
+
+fn to_v6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+fn common_prefix_len(a: &Ipv6Addr, b: &Ipv6Addr) -> u8 {
+    (u128::from(*a) ^ u128::from(*b)).leading_zeros() as u8
+}
+
+/// Returns the scope of an address (RFC 6724 section 3.1).
+fn scope(ip: IpAddr) -> u8 {
+    const LINK_LOCAL: u8 = 0x2;
+    const SITE_LOCAL: u8 = 0x5;
+    const GLOBAL:     u8 = 0xe;
+    match ip {
+        IpAddr::V4(ip) if ip.is_loopback() || ip.is_link_local() => LINK_LOCAL,
+        IpAddr::V4(..) => GLOBAL,
+        IpAddr::V6(ip) => {
+            let s = ip.segments();
+            if ip.is_multicast() {
+                (s[0] & 0xf) as u8
+            } else if ip.is_loopback() || s[0] & 0xffc0 == 0xfe80 {
+                LINK_LOCAL
+            } else if s[0] & 0xffc0 == 0xfec0 {
+                SITE_LOCAL
+            } else {
+                GLOBAL
+            }
+        },
+    }
+}
+
+/// Compares two destinations with their source addresses. Destinations that should be
+/// tried first are smaller.
+///
+/// Implements rules 1, 2, 5, 6, 8 and 9. There is no information about deprecated
+/// addresses, home addresses and the transport of an interface.
+fn compare(a: (IpAddr, Option<IpAddr>), b: (IpAddr, Option<IpAddr>),
+           policy: &Policy) -> Ordering {
+    let (da, sa) = a;
+    let (db, sb) = b;
+    // Rule 1: Avoid unusable destinations.
+    let (sa, sb) = match (sa, sb) {
+        (Some(sa), Some(sb)) => (sa, sb),
+        (sa, sb) => return sa.is_none().cmp(&sb.is_none()),
+    };
+    // Rule 2: Prefer matching scope.
+    let scope_a = scope(da) == scope(sa);
+    let scope_b = scope(db) == scope(sb);
+    if scope_a != scope_b {
+        return scope_b.cmp(&scope_a);
+    }
+    // Rule 5: Prefer matching label.
+    let label_a = policy.label(da) == policy.label(sa);
+    let label_b = policy.label(db) == policy.label(sb);
+    if label_a != label_b {
+        return label_b.cmp(&label_a);
+    }
+    // Rule 6: Prefer higher precedence.
+    let prec_a = policy.precedence(da);
+    let prec_b = policy.precedence(db);
+    if prec_a != prec_b {
+        return prec_b.cmp(&prec_a);
+    }
+    // Rule 8: Prefer smaller scope.
+    if scope(da) != scope(db) {
+        return scope(da).cmp(&scope(db));
+    }
+    // Rule 9: Use longest matching prefix. Only for IPv6 and only for the first 64 bits.
+    if let ((IpAddr::V6(da), IpAddr::V6(sa)), (IpAddr::V6(db), IpAddr::V6(sb)))
+            = ((da, sa), (db, sb)) {
+        let len_a = common_prefix_len(&da, &sa).min(64);
+        let len_b = common_prefix_len(&db, &sb).min(64);
+        return len_b.cmp(&len_a);
+    }
+    // Rule 10: Otherwise, leave the order unchanged.
+    Ordering::Equal
+}
+
+/// Sorts destinations by preference.
+///
+/// Each destination is paired with the source address that would be used to reach it,
+/// or `None` if it is unreachable. The sort is stable.
+pub fn sort(destinations: &mut [(IpAddr, Option<IpAddr>)], policy: &Policy) {
+    destinations.sort_by(|&a, &b| compare(a, b, policy));
+}
@@ -0,0 +1,955 @@
+//! Sender Policy Framework records.
+//!
+//! Provides parsing of SPF records and the `check_host()` function that evaluates them.
+//!
+//! See RFC 7208 for more details.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{str};
+
+use crate::{Data, Type};
+use crate::reverse;
+
+/// A parsed SPF record.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Spf {
+    /// The terms of the record in the order in which they appear.
+    pub terms: Vec<Term>,
+}
+
+/// A term of an SPF record.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Term {
+    /// A mechanism with its qualifier.
+    Directive(Qualifier, Mechanism),
+    /// A modifier.
+    Modifier(Modifier),
+}
+
+/// The qualifier of a directive.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Qualifier {
+    /// `+`
+    Pass,
+    /// `-`
+    Fail,
+    /// `~`
+    SoftFail,
+    /// `?`
+    Neutral,
+}
+
+/// A mechanism.
+///
+/// Domain specifications are stored unexpanded and can contain macros.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Mechanism {
+    /// Matches always.
+    All,
+    /// Domain specification.
+    Include(String),
+    /// Domain specification, IPv4 prefix length and IPv6 prefix length.
+    A(Option<String>, Option<u8>, Option<u8>),
+    /// Domain specification, IPv4 prefix length and IPv6 prefix length.
+    Mx(Option<String>, Option<u8>, Option<u8>),
+    /// Domain specification.
+    Ptr(Option<String>),
+    /// Network and prefix length.
+    Ip4(Ipv4Addr, Option<u8>),
+    /// Network and prefix length.
+    Ip6(Ipv6Addr, Option<u8>),
+    /// Domain specification.
+    Exists(String),
+}
+
+/// A modifier.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Modifier {
+    /// Domain specification.
+    Redirect(String),
+    /// Domain specification.
+    Explanation(String),
+    /// Name and value of a modifier that is not known to this library.
+    Unknown(String, String),
+}
+
+impl Spf {
+    /// Returns whether the text starts with the SPF version tag `v=spf1`.
+    ///
+    /// Only records for which this returns true are considered SPF records.
+    pub fn is_spf(src: &[u8]) -> bool {
+        let v = b"v=spf1";
+        src.len() >= v.len()
+            && src[..v.len()].eq_ignore_ascii_case(v)
+            && (src.len() == v.len() || src[v.len()] == b' ')
+    }
+
+    /// Parses the concatenated text of a TXT record as an SPF record.
+    ///
+    /// Fails on any syntax error, which corresponds to a `permerror` result.
+    pub fn parse(src: &[u8]) -> Result<Spf, ()> {
+        if !Spf::is_spf(src) {
+            return Err(());
+        }
+        let src = trycvt!(str::from_utf8(&src[b"v=spf1".len()..]));
+        let mut terms = vec!();
+        let mut redirect = false;
+        let mut explanation = false;
+        for term in src.split(' ') {
+            if term.is_empty() {
+                continue;
+            }
+            let term = parse_term(term)?;
+            match term {
+                Term::Modifier(Modifier::Redirect(..)) if redirect => return Err(()),
+                Term::Modifier(Modifier::Redirect(..)) => redirect = true,
+                Term::Modifier(Modifier::Explanation(..)) if explanation => return Err(()),
+                Term::Modifier(Modifier::Explanation(..)) => explanation = true,
+                _ => { },
+            }
+            terms.push(term);
+        }
+        Ok(Spf { terms: terms })
+    }
+
+    /// Returns the domain specification of the `redirect` modifier.
+    pub fn redirect(&self) -> Option<&str> {
+        for term in &self.terms {
+            if let Term::Modifier(Modifier::Redirect(ref domain)) = *term {
+                return Some(domain);
+            }
+        }
+        None
+    }
+
+    /// Returns the domain specification of the `exp` modifier.
+    pub fn explanation(&self) -> Option<&str> {
+        for term in &self.terms {
+            if let Term::Modifier(Modifier::Explanation(ref domain)) = *term {
+                return Some(domain);
+            }
+        }
+        None
+    }
+}
+
+/// The result of an SPF evaluation (RFC 7208 section 2.6).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SpfResult {
+    /// No SPF record was found or the domain is not valid.
+    None,
+    /// The domain makes no assertion about the client.
+    Neutral,
+    /// The client is authorized.
+    Pass,
+    /// The client is not authorized.
+    Fail,
+    /// The client is probably not authorized.
+    SoftFail,
+    /// A transient DNS error occurred.
+    TempError,
+    /// The published records could not be interpreted.
+    PermError,
+}
+
+impl SpfResult {
+    fn from_qualifier(qualifier: Qualifier) -> SpfResult {
+        match qualifier {
+            Qualifier::Pass     => SpfResult::Pass,
+            Qualifier::Fail     => SpfResult::Fail,
+            Qualifier::SoftFail => SpfResult::SoftFail,
+            Qualifier::Neutral  => SpfResult::Neutral,
+        }
+    }
+}
+
+/// The DNS lookups performed during an SPF evaluation.
+pub trait Lookup {
+    /// Returns the data of all records of type `ty` at `name`.
+    ///
+    /// An empty vector means that the name does not exist or has no such records. An
+    /// error means that the lookup failed temporarily.
+    fn lookup(&mut self, name: &str, ty: Type) -> Result<Vec<Data>, ()>;
+}
+
+/// The outcome of `check_host`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Evaluation {
+    /// The result.
+    pub result:      SpfResult,
+    /// The expanded `exp` text if the result is `Fail` and the domain provides one.
+    pub explanation: Option<String>,
+    /// A human readable description of each evaluation step.
+    pub trace:       Vec<String>,
+}
+
+/// Maximum number of terms that cause DNS lookups.
+const MAX_LOOKUPS: usize = 10;
+/// Maximum number of lookups that return no records.
+const MAX_VOID_LOOKUPS: usize = 2;
+/// Maximum number of MX and PTR names that are looked up per mechanism.
+const MAX_NAMES: usize = 10;
+
+/// Evaluates the SPF policy of `domain` for a message from `sender` delivered by `ip`.
+///
+/// `sender` is the MAIL FROM address or `postmaster@` followed by the HELO domain, and
+/// `helo` is the HELO domain. This implements the `check_host()` function of RFC 7208
+/// including the lookup limits of section 4.6.4.
+pub fn check_host<L: Lookup>(lookup: &mut L, ip: IpAddr, domain: &str, sender: &str,
+                             helo: &str) -> Evaluation {
+    // IPv4-mapped IPv6 addresses are evaluated as IPv4 addresses.
+    let ip = match ip {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, hi, lo] => IpAddr::V4(Ipv4Addr::new(
+                (hi >> 8) as u8, hi as u8, (lo >> 8) as u8, lo as u8)),
+            _ => ip,
+        },
+        _ => ip,
+    };
+    let (local, sender_domain) = match sender.rfind('@') {
+        Some(pos) if pos > 0 => (&sender[..pos], &sender[pos+1..]),
+        Some(pos) => ("postmaster", &sender[pos+1..]),
+        _ => ("postmaster", sender),
+    };
+    let mut eval = Evaluator {
+        lookup:        lookup,
+        ip:            ip,
+        local:         local.to_string(),
+        sender_domain: sender_domain.to_string(),
+        helo:          helo.to_string(),
+        lookups:       0,
+        void_lookups:  0,
+        trace:         vec!(),
+    };
+    let (result, explanation) = eval.check_host(domain);
+    eval.trace.push(format!("result: {:?}", result));
+    Evaluation {
+        result:      result,
+        explanation: explanation,
+        trace:       eval.trace,
+    }
+}
+
+struct Evaluator<'a, L: 'a> {
+    lookup:        &'a mut L,
+    ip:            IpAddr,
+    local:         String,
+    sender_domain: String,
+    helo:          String,
+    lookups:       usize,
+    void_lookups:  usize,
+    trace:         Vec<String>,
+}
+
+impl<'a, L: Lookup> Evaluator<'a, L> {
+    fn check_host(&mut self, domain: &str) -> (SpfResult, Option<String>) {
+        let domain = domain.trim_end_matches('.');
+        if !is_valid_domain(domain) {
+            self.trace.push(format!("{}: not a valid domain", domain));
+            return (SpfResult::None, None);
+        }
+        let spf = match self.record(domain) {
+            Ok(spf) => spf,
+            Err(result) => return (result, None),
+        };
+        for term in &spf.terms {
+            let (qualifier, mechanism) = match *term {
+                Term::Directive(q, ref m) => (q, m),
+                _ => continue,
+            };
+            match self.mechanism(mechanism, domain) {
+                Ok(true) => {
+                    let result = SpfResult::from_qualifier(qualifier);
+                    self.trace.push(format!("{}: {:?} matched, result {:?}", domain,
+                                            mechanism, result));
+                    let explanation = match (result, spf.explanation()) {
+                        (SpfResult::Fail, Some(exp)) => self.explanation(exp, domain),
+                        _ => None,
+                    };
+                    return (result, explanation);
+                },
+                Ok(false) => self.trace.push(format!("{}: {:?} did not match", domain,
+                                                     mechanism)),
+                Err(result) => {
+                    self.trace.push(format!("{}: {:?} failed with {:?}", domain,
+                                            mechanism, result));
+                    return (result, None);
+                },
+            }
+        }
+        if let Some(redirect) = spf.redirect() {
+            if let Err(result) = self.count_lookup() {
+                return (result, None);
+            }
+            let target = match self.expand(redirect, domain, false) {
+                Ok(t) => t,
+                Err(result) => return (result, None),
+            };
+            self.trace.push(format!("{}: redirect to {}", domain, target));
+            return match self.check_host(&target) {
+                (SpfResult::None, _) => (SpfResult::PermError, None),
+                res => res,
+            };
+        }
+        self.trace.push(format!("{}: no mechanism matched", domain));
+        (SpfResult::Neutral, None)
+    }
+
+    /// Retrieves and parses the SPF record of `domain`.
+    fn record(&mut self, domain: &str) -> Result<Spf, SpfResult> {
+        let txts = match self.lookup.lookup(domain, Type::Txt) {
+            Ok(v) => v,
+            Err(_) => {
+                self.trace.push(format!("{}: TXT lookup failed", domain));
+                return Err(SpfResult::TempError);
+            },
+        };
+        let mut records: Vec<_> = txts.iter().filter_map(|d| d.txt())
+                                      .filter(|t| Spf::is_spf(t)).collect();
+        match records.len() {
+            0 => {
+                self.trace.push(format!("{}: no SPF record", domain));
+                Err(SpfResult::None)
+            },
+            1 => {
+                let record = records.pop().unwrap();
+                self.trace.push(format!("{}: {}", domain,
+                                        String::from_utf8_lossy(&record)));
+                match Spf::parse(&record) {
+                    Ok(spf) => Ok(spf),
+                    _ => {
+                        self.trace.push(format!("{}: syntax error", domain));
+                        Err(SpfResult::PermError)
+                    },
+                }
+            },
+            _ => {
+                self.trace.push(format!("{}: multiple SPF records", domain));
+                Err(SpfResult::PermError)
+            },
+        }
+    }
+
+    /// Returns whether the mechanism matches the client or the result with which the
+    /// evaluation has to be aborted.
+    fn mechanism(&mut self, mechanism: &Mechanism, domain: &str) -> Result<bool, SpfResult> {
+        match *mechanism {
+            Mechanism::All => Ok(true),
+            Mechanism::Include(ref spec) => {
+                self.count_lookup()?;
+                let target = self.expand(spec, domain, false)?;
+                self.trace.push(format!("{}: include {}", domain, target));
+                match self.check_host(&target).0 {
+                    SpfResult::Pass => Ok(true),
+                    SpfResult::Fail | SpfResult::SoftFail | SpfResult::Neutral => Ok(false),
+                    SpfResult::TempError => Err(SpfResult::TempError),
+                    SpfResult::PermError | SpfResult::None => Err(SpfResult::PermError),
+                }
+            },
+            Mechanism::A(ref spec, ip4, ip6) => {
+                self.count_lookup()?;
+                let target = self.target(spec, domain)?;
+                let addrs = self.addresses(&target, true)?;
+                Ok(addrs.into_iter().any(|a| in_network(self.ip, a, ip4, ip6)))
+            },
+            Mechanism::Mx(ref spec, ip4, ip6) => {
+                self.count_lookup()?;
+                let target = self.target(spec, domain)?;
+                let mut exchanges = vec!();
+                for data in self.query(&target, Type::Mx, true)? {
+                    if let Data::Mx(_, name) = data {
+                        exchanges.push(name);
+                    }
+                }
+                if exchanges.len() > MAX_NAMES {
+                    self.trace.push(format!("{}: too many MX records", target));
+                    return Err(SpfResult::PermError);
+                }
+                for exchange in exchanges {
+                    let addrs = self.addresses(&exchange, false)?;
+                    if addrs.into_iter().any(|a| in_network(self.ip, a, ip4, ip6)) {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            },
+            Mechanism::Ptr(ref spec) => {
+                self.count_lookup()?;
+                let target = self.target(spec, domain)?.to_ascii_lowercase();
+                let names = self.validated_names()?;
+                Ok(names.iter().any(|n| {
+                    let n = n.trim_end_matches('.').to_ascii_lowercase();
+                    n == target || n.ends_with(&format!(".{}", target))
+                }))
+            },
+            Mechanism::Ip4(net, len) => {
+                Ok(in_network(self.ip, IpAddr::V4(net), Some(len.unwrap_or(32)), None))
+            },
+            Mechanism::Ip6(net, len) => {
+                Ok(in_network(self.ip, IpAddr::V6(net), None, Some(len.unwrap_or(128))))
+            },
+            Mechanism::Exists(ref spec) => {
+                self.count_lookup()?;
+                let target = self.expand(spec, domain, false)?;
+                Ok(!self.query(&target, Type::A, true)?.is_empty())
+            },
+        }
+    }
+
+    fn count_lookup(&mut self) -> Result<(), SpfResult> {
+        self.lookups += 1;
+        if self.lookups > MAX_LOOKUPS {
+            self.trace.push(format!("more than {} DNS lookups", MAX_LOOKUPS));
+            return Err(SpfResult::PermError);
+        }
+        Ok(())
+    }
+
+    /// Performs a lookup. If `void` is set, empty results count towards the void lookup
+    /// limit.
+    fn query(&mut self, name: &str, ty: Type, void: bool) -> Result<Vec<Data>, SpfResult> {
+        let res = match self.lookup.lookup(name, ty) {
+            Ok(res) => res,
+            _ => {
+                self.trace.push(format!("{}: {:?} lookup failed", name, ty));
+                return Err(SpfResult::TempError);
+            },
+        };
+        if void && res.is_empty() {
+            self.void_lookups += 1;
+            if self.void_lookups > MAX_VOID_LOOKUPS {
+                self.trace.push(format!("more than {} void lookups", MAX_VOID_LOOKUPS));
+                return Err(SpfResult::PermError);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Returns the addresses of `name` of the same family as the client.
+    fn addresses(&mut self, name: &str, void: bool) -> Result<Vec<IpAddr>, SpfResult> {
+        let ty = match self.ip {
+            IpAddr::V4(..) => Type::A,
+            IpAddr::V6(..) => Type::Aaaa,
+        };
+        let mut res = vec!();
+        for data in self.query(name, ty, void)? {
+            match data {
+                Data::A(addr) => res.push(IpAddr::V4(addr)),
+                Data::Aaaa(addr) => res.push(IpAddr::V6(addr)),
+                _ => { },
+            }
+        }
+        Ok(res)
+    }
+
+    /// Returns the names of the client whose forward lookup contains the client.
+    fn validated_names(&mut self) -> Result<Vec<String>, SpfResult> {
+        // A failed PTR lookup means that there are no names (RFC 7208 section 5.5).
+        let ptrs = match self.query(&reverse::name(self.ip), Type::Ptr, true) {
+            Ok(ptrs) => ptrs,
+            Err(SpfResult::TempError) => vec!(),
+            Err(e) => return Err(e),
+        };
+        let mut names = vec!();
+        for data in ptrs {
+            if let Data::Ptr(name) = data {
+                names.push(name);
+            }
+        }
+        names.truncate(MAX_NAMES);
+        let mut res = vec!();
+        for name in names {
+            // Errors during the validation are ignored.
+            let addrs = self.addresses(&name, false).unwrap_or(vec!());
+            if addrs.contains(&self.ip) {
+                res.push(name);
+            }
+        }
+        Ok(res)
+    }
+
+    fn target(&mut self, spec: &Option<String>, domain: &str) -> Result<String, SpfResult> {
+        match *spec {
+            Some(ref spec) => self.expand(spec, domain, false),
+            _ => Ok(domain.to_string()),
+        }
+    }
+
+    /// Retrieves and expands the explanation string of the `exp` modifier.
+    fn explanation(&mut self, spec: &str, domain: &str) -> Option<String> {
+        let target = match self.expand(spec, domain, false) {
+            Ok(t) => t,
+            _ => return None,
+        };
+        let txts = match self.lookup.lookup(&target, Type::Txt) {
+            Ok(v) => v,
+            _ => return None,
+        };
+        if txts.len() != 1 {
+            return None;
+        }
+        let text = match txts[0].txt().map(String::from_utf8) {
+            Some(Ok(text)) => text,
+            _ => return None,
+        };
+        if !is_macro_string(&text.replace(" ", "%_")) {
+            return None;
+        }
+        self.expand(&text, domain, true).ok()
+    }
+
+    /// Expands the macros in `s` (RFC 7208 section 7).
+    ///
+    /// `explanation` enables the macros that are only allowed in explanation strings.
+    /// Expanded domain specifications are shortened to 253 characters.
+    fn expand(&mut self, s: &str, domain: &str, explanation: bool) -> Result<String, SpfResult> {
+        let mut res = String::new();
+        let mut rest = s;
+        while let Some(pos) = rest.find('%') {
+            res.push_str(&rest[..pos]);
+            rest = &rest[pos+1..];
+            if rest.starts_with("%") {
+                res.push('%');
+                rest = &rest[1..];
+            } else if rest.starts_with("_") {
+                res.push(' ');
+                rest = &rest[1..];
+            } else if rest.starts_with("-") {
+                res.push_str("%20");
+                rest = &rest[1..];
+            } else if rest.starts_with("{") {
+                let end = match rest.find('}') {
+                    Some(end) => end,
+                    _ => return Err(SpfResult::PermError),
+                };
+                let value = self.macro_value(&rest[1..end], domain, explanation)?;
+                res.push_str(&value);
+                rest = &rest[end+1..];
+            } else {
+                return Err(SpfResult::PermError);
+            }
+        }
+        res.push_str(rest);
+        if !explanation {
+            while res.len() > 253 {
+                match res.find('.') {
+                    Some(pos) => { res = res[pos+1..].to_string(); },
+                    _ => break,
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    fn macro_value(&mut self, spec: &str, domain: &str,
+                   explanation: bool) -> Result<String, SpfResult> {
+        if !is_macro_expand(spec.as_bytes()) {
+            return Err(SpfResult::PermError);
+        }
+        let letter = spec.as_bytes()[0];
+        let value = match letter.to_ascii_lowercase() {
+            b's' => format!("{}@{}", self.local, self.sender_domain),
+            b'l' => self.local.clone(),
+            b'o' => self.sender_domain.clone(),
+            b'd' => domain.to_string(),
+            b'i' => match self.ip {
+                IpAddr::V4(..) => format!("{}", self.ip),
+                IpAddr::V6(..) => nibbles(self.ip).join("."),
+            },
+            b'p' => {
+                let names = self.validated_names().unwrap_or(vec!());
+                let exact = names.iter().find(|n| n.eq_ignore_ascii_case(domain));
+                match exact.or(names.first()) {
+                    Some(name) => name.trim_end_matches('.').to_string(),
+                    _ => "unknown".to_string(),
+                }
+            },
+            b'v' => match self.ip {
+                IpAddr::V4(..) => "in-addr".to_string(),
+                IpAddr::V6(..) => "ip6".to_string(),
+            },
+            b'h' => self.helo.clone(),
+            b'c' if explanation => format!("{}", self.ip),
+            b'r' if explanation => "unknown".to_string(),
+            b't' if explanation => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH);
+                format!("{}", now.map(|d| d.as_secs()).unwrap_or(0))
+            },
+            _ => return Err(SpfResult::PermError),
+        };
+
+        // Transformers.
+        let mut rest = &spec[1..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let keep = match &rest[..digits] {
+            "" => None,
+            n => match n.parse() {
+                Ok(0) | Err(_) => return Err(SpfResult::PermError),
+                Ok(n) => Some(n),
+            },
+        };
+        rest = &rest[digits..];
+        let reverse = rest.starts_with("r") || rest.starts_with("R");
+        if reverse {
+            rest = &rest[1..];
+        }
+        let delimiters = if rest.is_empty() { "." } else { rest };
+        let mut parts: Vec<&str> = value.split(|c| delimiters.contains(c)).collect();
+        if reverse {
+            parts.reverse();
+        }
+        if let Some(keep) = keep {
+            if keep < parts.len() {
+                let skip = parts.len() - keep;
+                parts = parts[skip..].to_vec();
+            }
+        }
+        let value = parts.join(".");
+
+        if (letter as char).is_uppercase() {
+            Ok(url_escape(&value))
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+/// Returns whether the domain is a syntactically valid multi-label domain.
+fn is_valid_domain(domain: &str) -> bool {
+    !domain.is_empty() && domain.len() <= 253 && domain.contains('.')
+        && domain.split('.').all(|l| !l.is_empty() && l.len() <= 63)
+}
+
+fn in_network(ip: IpAddr, net: IpAddr, ip4: Option<u8>, ip6: Option<u8>) -> bool {
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let len = ip4.unwrap_or(32) as u32;
+            let mask = if len == 0 { 0 } else { !0u32 << (32 - len) };
+            let ip = u32_from(&ip.octets());
+            let net = u32_from(&net.octets());
+            ip & mask == net & mask
+        },
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let len = ip6.unwrap_or(128) as usize;
+            let ip = ip.segments();
+            let net = net.segments();
+            (0..8).all(|i| {
+                let bits = len.saturating_sub(16 * i).min(16);
+                let mask = if bits == 0 { 0 } else { !0u16 << (16 - bits) };
+                ip[i] & mask == net[i] & mask
+            })
+        },
+        _ => false,
+    }
+}
+
+fn u32_from(octets: &[u8; 4]) -> u32 {
+    (octets[0] as u32) << 24 | (octets[1] as u32) << 16 | (octets[2] as u32) << 8
+        | octets[3] as u32
+}
+
+/// Returns the hexadecimal nibbles of an IPv6 address in order.
+fn nibbles(ip: IpAddr) -> Vec<String> {
+    let mut res = vec!();
+    if let IpAddr::V6(ip) = ip {
+        for seg in ip.segments().iter() {
+            for shift in [12, 8, 4, 0].iter() {
+                res.push(format!("{:x}", (seg >> *shift) & 0xf));
+            }
+        }
+    }
+    res
+}
+
+fn url_escape(s: &str) -> String {
+    let mut res = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'-' || b == b'.' || b == b'_' || b == b'~' {
+            res.push(b as char);
+        } else {
+            res.push_str(&format!("%{:02X}", b));
+        }
+    }
+    res
+}
+
+fn parse_term(term: &str) -> Result<Term, ()> {
+    let name_end = term.find(['=', ':', '/']).unwrap_or(term.len());
+    if term[name_end..].starts_with("=") {
+        return parse_modifier(&term[..name_end], &term[name_end+1..]);
+    }
+
+    let (qualifier, term) = match term.as_bytes()[0] {
+        b'+' => (Qualifier::Pass, &term[1..]),
+        b'-' => (Qualifier::Fail, &term[1..]),
+        b'~' => (Qualifier::SoftFail, &term[1..]),
+        b'?' => (Qualifier::Neutral, &term[1..]),
+        _ => (Qualifier::Pass, term),
+    };
+    let name_end = term.find([':', '/']).unwrap_or(term.len());
+    let name = term[..name_end].to_ascii_lowercase();
+    let arg = &term[name_end..];
+    let domain = |arg: &str| -> Result<Option<String>, ()> {
+        if arg.is_empty() {
+            Ok(None)
+        } else if arg.starts_with(":") && is_domain_spec(&arg[1..]) {
+            Ok(Some(arg[1..].to_string()))
+        } else {
+            Err(())
+        }
+    };
+    let mechanism = match &name[..] {
+        "all" if arg.is_empty() => Mechanism::All,
+        "include" => Mechanism::Include(required(domain(arg)?)?),
+        "exists" => Mechanism::Exists(required(domain(arg)?)?),
+        "ptr" => Mechanism::Ptr(domain(arg)?),
+        "a" | "mx" => {
+            let (arg, ip4, ip6) = dual_cidr(arg)?;
+            let domain = domain(arg)?;
+            if name == "a" {
+                Mechanism::A(domain, ip4, ip6)
+            } else {
+                Mechanism::Mx(domain, ip4, ip6)
+            }
+        },
+        "ip4" if arg.starts_with(":") => {
+            let (net, len) = cidr(&arg[1..], 32)?;
+            Mechanism::Ip4(trycvt!(net.parse()), len)
+        },
+        "ip6" if arg.starts_with(":") => {
+            let (net, len) = cidr(&arg[1..], 128)?;
+            Mechanism::Ip6(trycvt!(net.parse()), len)
+        },
+        _ => return Err(()),
+    };
+    Ok(Term::Directive(qualifier, mechanism))
+}
+
+fn parse_modifier(name: &str, value: &str) -> Result<Term, ()> {
+    let bytes = name.as_bytes();
+    let valid_name = !bytes.is_empty() && bytes[0].is_ascii_alphabetic()
+        && bytes.iter().all(|&b| {
+            b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'.'
+        });
+    if !valid_name {
+        return Err(());
+    }
+    let modifier = match &name.to_ascii_lowercase()[..] {
+        "redirect" | "exp" if !is_domain_spec(value) => return Err(()),
+        "redirect" => Modifier::Redirect(value.to_string()),
+        "exp" => Modifier::Explanation(value.to_string()),
+        _ if !is_macro_string(value) => return Err(()),
+        _ => Modifier::Unknown(name.to_string(), value.to_string()),
+    };
+    Ok(Term::Modifier(modifier))
+}
+
+fn required(domain: Option<String>) -> Result<String, ()> {
+    match domain {
+        Some(d) => Ok(d),
+        _ => Err(()),
+    }
+}
+
+/// Splits a trailing `/n` off a network.
+fn cidr(s: &str, max: u8) -> Result<(&str, Option<u8>), ()> {
+    match s.rfind('/') {
+        Some(pos) => Ok((&s[..pos], Some(prefix_len(&s[pos+1..], max)?))),
+        _ => Ok((s, None)),
+    }
+}
+
+/// Splits trailing `/n`, `//m` or `/n//m` off a domain specification.
+fn dual_cidr(s: &str) -> Result<(&str, Option<u8>, Option<u8>), ()> {
+    let mut s = s;
+    let mut ip6 = None;
+    if let Some(pos) = s.find("//") {
+        ip6 = Some(prefix_len(&s[pos+2..], 128)?);
+        s = &s[..pos];
+    }
+    let mut ip4 = None;
+    if let Some(pos) = s.rfind('/') {
+        ip4 = Some(prefix_len(&s[pos+1..], 32)?);
+        s = &s[..pos];
+    }
+    Ok((s, ip4, ip6))
+}
+
+fn prefix_len(s: &str, max: u8) -> Result<u8, ()> {
+    // Leading zeros are not allowed.
+    if s.is_empty() || (s.len() > 1 && s.starts_with("0"))
+            || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(());
+    }
+    match s.parse() {
+        Ok(n) if n <= max => Ok(n),
+        _ => Err(()),
+    }
+}
+
+/// Returns whether `s` is a syntactically valid domain specification.
+fn is_domain_spec(s: &str) -> bool {
+    if !is_macro_string(s) {
+        return false;
+    }
+    // The domain-end is either a macro or a top-level label that is not all-numeric.
+    let s = s.trim_end_matches('.');
+    if s.ends_with("}") {
+        return true;
+    }
+    let top = match s.rfind('.') {
+        Some(pos) => &s[pos+1..],
+        _ => return false,
+    };
+    !top.is_empty() && !top.bytes().all(|b| b.is_ascii_digit()) && top.bytes().all(|b| {
+        b.is_ascii_alphanumeric() || b == b'-'
+    }) && !top.starts_with("-") && !top.ends_with("-")
+}
+
+/// Returns whether `s` is a syntactically valid macro string.
+fn is_macro_string(s: &str) -> bool {
+    let mut s = s.as_bytes();
+    while !s.is_empty() {
+        if s[0] != b'%' {
+            if s[0] < 0x21 || s[0] > 0x7e {
+                return false;
+            }
+            s = &s[1..];
+            continue;
+        }
+        if s.len() < 2 {
+            return false;
+        }
+        match s[1] {
+            b'%' | b'_' | b'-' => s = &s[2..],
+            b'{' => match s.iter().position(|&b| b == b'}') {
+                Some(end) if is_macro_expand(&s[2..end]) => s = &s[end+1..],
+                _ => return false,
+            },
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Returns whether the contents of `%{...}` are valid.
+fn is_macro_expand(s: &[u8]) -> bool {
+    if s.is_empty() {
+        return false;
+    }
+    match s[0].to_ascii_lowercase() {
+        b's' | b'l' | b'o' | b'd' | b'i' | b'p' | b'h' | b'c' | b'r' | b't' | b'v' => { },
+        _ => return false,
+    }
+    let mut s = &s[1..];
+    while !s.is_empty() && s[0].is_ascii_digit() {
+        s = &s[1..];
+    }
+    if !s.is_empty() && (s[0] == b'r' || s[0] == b'R') {
+        s = &s[1..];
+    }
+    s.iter().all(|&b| is_delimiter(b))
+}
+
+fn is_delimiter(b: u8) -> bool {
+    matches!(b, b'.' | b'-' | b'+' | b',' | b'/' | b'_' | b'=')
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Answer = Result<Vec<Data>, ()>;
+
+    /// Answers lookups from a fixed list. Other names have no records.
+    struct Zone(Vec<(&'static str, Type, Answer)>);
+
+    impl Lookup for Zone {
+        fn lookup(&mut self, name: &str, ty: Type) -> Result<Vec<Data>, ()> {
+            let name = name.trim_end_matches('.');
+            match self.0.iter().find(|e| e.0.eq_ignore_ascii_case(name) && e.1 == ty) {
+                Some(e) => e.2.clone(),
+                _ => Ok(vec!()),
+            }
+        }
+    }
+
+    fn txt(s: &str) -> Answer {
+        Ok(vec!(Data::Txt(vec!(s.as_bytes().to_vec()))))
+    }
+
+    fn expand(ip: &str, spec: &str) -> String {
+        let mut zone = Zone(vec!());
+        let mut eval = Evaluator {
+            lookup:        &mut zone,
+            ip:            ip.parse().unwrap(),
+            local:         "strong-bad".to_string(),
+            sender_domain: "email.example.com".to_string(),
+            helo:          "mx.example.org".to_string(),
+            lookups:       0,
+            void_lookups:  0,
+            trace:         vec!(),
+        };
+        eval.expand(spec, "email.example.com", false).unwrap()
+    }
+
+    /// The examples of RFC 7208 section 7.4.
+    #[test]
+    fn rfc7208_macros() {
+        let examples = [
+            ("%{s}", "strong-bad@email.example.com"),
+            ("%{o}", "email.example.com"),
+            ("%{d}", "email.example.com"),
+            ("%{d4}", "email.example.com"),
+            ("%{d3}", "email.example.com"),
+            ("%{d2}", "example.com"),
+            ("%{d1}", "com"),
+            ("%{dr}", "com.example.email"),
+            ("%{d2r}", "example.email"),
+            ("%{l}", "strong-bad"),
+            ("%{l-}", "strong.bad"),
+            ("%{lr}", "strong-bad"),
+            ("%{lr-}", "bad.strong"),
+            ("%{l1r-}", "strong"),
+            ("%{ir}.%{v}._spf.%{d2}", "3.2.0.192.in-addr._spf.example.com"),
+            ("%{lr-}.lp._spf.%{d2}", "bad.strong.lp._spf.example.com"),
+            ("%{lr-}.lp.%{ir}.%{v}._spf.%{d2}",
+             "bad.strong.lp.3.2.0.192.in-addr._spf.example.com"),
+            ("%{ir}.%{v}.%{l1r-}.lp._spf.%{d2}",
+             "3.2.0.192.in-addr.strong.lp._spf.example.com"),
+            ("%{d2}.trusted-domains.example.net", "example.com.trusted-domains.example.net"),
+        ];
+        for &(spec, expanded) in &examples {
+            assert_eq!(expand("192.0.2.3", spec), expanded, "{}", spec);
+        }
+        assert_eq!(expand("2001:db8::cb01", "%{ir}.%{v}._spf.%{d2}"),
+                   "1.0.b.c.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6._spf.\
+                    example.com");
+    }
+
+    #[test]
+    fn check_host_ip4() {
+        let spf = txt("v=spf1 ip4:192.0.2.0/24 -all");
+        let mut zone = Zone(vec!(("example.com", Type::Txt, spf)));
+        let check = |zone: &mut Zone, ip: &str| {
+            check_host(zone, ip.parse().unwrap(), "example.com", "a@example.com",
+                       "mx.example.com").result
+        };
+        assert_eq!(check(&mut zone, "192.0.2.3"), SpfResult::Pass);
+        assert_eq!(check(&mut zone, "198.51.100.1"), SpfResult::Fail);
+    }
+
+    /// A failed PTR lookup makes the `ptr` mechanism not match (RFC 7208 section 5.5).
+    #[test]
+    fn ptr_lookup_failure() {
+        let mut zone = Zone(vec!(
+            ("example.com", Type::Txt, txt("v=spf1 ptr -all")),
+            ("3.2.0.192.in-addr.arpa", Type::Ptr, Err(())),
+        ));
+        let eval = check_host(&mut zone, "192.0.2.3".parse().unwrap(), "example.com",
+                              "a@example.com", "mx.example.com");
+        assert_eq!(eval.result, SpfResult::Fail);
+    }
+}
@@ -0,0 +1,44 @@
+//! Tag-value lists as used by DKIM and DMARC records (RFC 6376 section 3.2).
+
+use core::{str};
+
+use alloc::vec::{Vec};
+use alloc::string::{String, ToString};
+
+/// Splits a tag list into its tag-value pairs.
+///
+/// Whitespace around tags and values is removed. A trailing `;` is allowed. Duplicate
+/// tags are an error.
+pub fn parse(src: &[u8]) -> Result<Vec<(String, String)>, ()> {
+    let src = trycvt!(str::from_utf8(src));
+    let mut res: Vec<(String, String)> = vec!();
+    for spec in src.split(';') {
+        if spec.trim().is_empty() {
+            continue;
+        }
+        let pos = match spec.find('=') {
+            Some(p) => p,
+            _ => return Err(()),
+        };
+        let tag = spec[..pos].trim();
+        let value = spec[pos+1..].trim();
+        if !is_tag_name(tag) || res.iter().any(|t| t.0 == tag) {
+            return Err(());
+        }
+        res.push((tag.to_string(), value.to_string()));
+    }
+    Ok(res)
+}
+
+fn is_tag_name(tag: &str) -> bool {
+    let bytes = tag.as_bytes();
+    !bytes.is_empty()
+        && bytes[0].is_ascii_alphabetic()
+        && bytes.iter().all(|&b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Removes all whitespace from a value. Used for base64 and similar values that may be
+/// folded.
+pub fn strip_whitespace(value: &str) -> String {
+    value.chars().filter(|c| !c.is_whitespace()).collect()
+}
@@ -1,49 +1,147 @@
 //! Toy DNS API. Only available on linux.
 
-use std::net::{IpAddr, UdpSocket};
-use std::time::{Duration};
-use std::io::{self, BufReader, BufRead};
-use std::fs::{File};
-
-use {Data, Packet, Type, Question, Class};
-
-#[cfg(unix)]
-fn set_timeout(socket: &mut UdpSocket, mut duration: Duration) -> Result<(), ()> {
-    use libc::{timeval, setsockopt, time_t, suseconds_t, socklen_t, c_int, SOL_SOCKET};
-    use std::{mem};
-    use std::os::unix::{AsRawFd};
-
-    #[cfg(target_os = "linux")]
-    const SO_RCVTIMEO: c_int = 20;
-
-    let seconds = duration.num_seconds() as time_t;
-    duration = duration - Duration::seconds(duration.num_seconds());
-    let useconds = duration.num_microseconds().unwrap() as suseconds_t;
-
-    let fd = socket.as_raw_fd();
-    let timeval = timeval {
-        tv_sec: seconds,
-        tv_usec: useconds,
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+use std::io::{self, Read, Write};
+use std::{error, fmt, fs, thread};
+use std::sync::{Mutex, OnceLock, mpsc};
+use std::sync::atomic::{self, AtomicUsize};
+
+use crate::{Data, Packet, Record, Type, Question, Class, ResponseCode, Edns};
+use crate::{DEFAULT_UDP_PAYLOAD_SIZE};
+use crate::resolv::{ResolverConfig};
+use crate::hosts::{self, HostsFile, Source};
+use crate::cache::{Cache, CacheConfig};
+use crate::selection::{self, Policy};
+use crate::spf;
+use crate::random;
+use crate::reverse as rev;
+
+/// Number of random source ports that are tried before the port is left to the OS.
+const PORT_ATTEMPTS: usize = 8;
+
+/// Binds a socket for queries to `server` to a random port (RFC 5452 section 9.2).
+fn get_socket(server: SocketAddr) -> io::Result<UdpSocket> {
+    let ip = match server {
+        SocketAddr::V4(..) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(..) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
     };
+    for _ in 0..PORT_ATTEMPTS {
+        let port = match random::u16() {
+            Ok(p) if p >= 1024 => p,
+            Ok(_) => continue,
+            _ => break,
+        };
+        if let Ok(socket) = UdpSocket::bind((ip, port)) {
+            return Ok(socket);
+        }
+    }
+    UdpSocket::bind((ip, 0))
+}
 
-    unsafe {
-        if setsockopt(fd, SOL_SOCKET, SO_RCVTIMEO, &timeval as *const _ as *const _,
-                      mem::size_of_val(&timeval) as socklen_t) == 0 {
-            Ok(())
+/// Randomizes the case of the ASCII letters of a name. Other characters are kept.
+fn randomize_case(name: &str) -> Result<String, ()> {
+    let mut bits = vec!(0; name.len() / 8 + 1);
+    random::fill(&mut bits)?;
+    Ok(name.chars().enumerate().map(|(i, c)| {
+        if bits[i / 8] & (1 << (i % 8)) != 0 {
+            c.to_ascii_uppercase()
         } else {
-            Err(())
+            c.to_ascii_lowercase()
         }
+    }).collect())
+}
+
+/// Returns the resolver configuration of the system.
+///
+/// See `ResolverConfig::system`. If `/etc/resolv.conf` cannot be read, the default
+/// configuration is used.
+pub fn config() -> ResolverConfig {
+    load_config().0
+}
+
+/// Returns the resolver configuration of the system like `config` and whether
+/// `/etc/resolv.conf` could be read.
+fn load_config() -> (ResolverConfig, bool) {
+    match ResolverConfig::system() {
+        Ok(conf) => (conf, true),
+        _ => {
+            let mut conf = ResolverConfig::default();
+            conf.apply_env();
+            (conf, false)
+        },
     }
 }
 
-fn get_socket() -> io::Result<UdpSocket> {
-    UdpSocket::bind(&(IpAddr::new_v4(0,0,0,0), 0))
+/// A response that does not contain the requested records.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ErrorResponse {
+    /// Response code.
+    pub response_code: ResponseCode,
+    /// Nameserver that sent the response, or `None` if it came from the cache.
+    pub server:        Option<SocketAddr>,
+    /// SOA record of the authority section, if any.
+    pub soa:           Option<Box<Record>>,
+}
+
+/// The reason why a lookup failed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ResolveError {
+    /// `/etc/resolv.conf` cannot be read and there is no nameserver on the loopback
+    /// address.
+    NoConfig,
+    /// No nameserver responded in time or the nameservers could not be reached.
+    Timeout,
+    /// The name does not exist.
+    NameError(ErrorResponse),
+    /// The name exists but has no records of the requested type.
+    NoRecords(ErrorResponse),
+    /// The nameservers failed, e.g., with `ServerFailure` or `Refused`.
+    ServerError(ErrorResponse),
+    /// The name is not in the hosts file and the nameservers are not consulted (see
+    /// `hosts::sources`).
+    NotFound,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (msg, response) = match *self {
+            ResolveError::NoConfig           => ("no resolv.conf and no nameserver", None),
+            ResolveError::Timeout            => ("no nameserver responded", None),
+            ResolveError::NameError(ref r)   => ("the name does not exist", Some(r)),
+            ResolveError::NoRecords(ref r)   => ("no records of this type", Some(r)),
+            ResolveError::ServerError(ref r) => ("the nameserver failed", Some(r)),
+            ResolveError::NotFound           => ("the name is not in the hosts file", None),
+        };
+        f.write_str(msg)?;
+        match response {
+            Some(&ErrorResponse { response_code, server: Some(server), .. }) => {
+                write!(f, " ({:?} from {})", response_code, server)
+            },
+            Some(&ErrorResponse { response_code, server: None, .. }) => {
+                write!(f, " ({:?} from the cache)", response_code)
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+impl error::Error for ResolveError { }
+
+/// Reports a timeout as a missing configuration if `/etc/resolv.conf` could not be read
+/// when the configuration was loaded.
+fn no_config(e: ResolveError, found: bool) -> ResolveError {
+    if e == ResolveError::Timeout && !found {
+        ResolveError::NoConfig
+    } else {
+        e
+    }
 }
 
 fn get_ips_int(hostname: &str, timeout: Option<Duration>,
-               ty: Type) -> Result<Vec<IpAddr>, ()> {
+               ty: Type) -> Result<Vec<IpAddr>, ResolveError> {
     let mut res = vec!();
-    for data in try!(query_int(hostname, ty, timeout)) {
+    for data in try_query(hostname, ty, timeout)? {
         match data {
             Data::A(addr) => res.push(IpAddr::V4(addr)),
             Data::Aaaa(addr) => res.push(IpAddr::V6(addr)),
@@ -53,71 +151,934 @@ fn get_ips_int(hostname: &str, timeout: Option<Duration>,
     Ok(res)
 }
 
-#[cfg(unix)]
-fn nameservers_int() -> Result<Vec<IpAddr>, ()> {
+/// Retrieves a list of nameservers from the OS.
+pub fn nameservers() -> Vec<IpAddr> {
+    config().nameservers.iter().map(|s| s.ip()).collect()
+}
+
+/// Returns the addresses of this hostname or an empty vector if the lookup fails.
+///
+/// See `try_ips`.
+pub fn ips(hostname: &str, timeout: Option<Duration>) -> Vec<IpAddr> {
+    try_ips(hostname, timeout).unwrap_or_default()
+}
+
+/// Returns the addresses of this hostname.
+///
+/// The hosts file and the nameservers are consulted in the order of the `hosts` line in
+/// `/etc/nsswitch.conf` until one of them knows the name. The addresses are sorted with
+/// `sort_ips` and the policy from `policy`, and then by the sortlist of the resolver
+/// configuration (see `ResolverConfig::apply_sortlist`).
+///
+/// Names are qualified with the search list of the resolver configuration unless they
+/// end with a dot (see `ResolverConfig::candidates`). `timeout` is used like in `query`.
+///
+/// If no source knows the name, the error of the nameservers is returned. If the
+/// nameservers have neither A nor AAAA records, the error of the A query is preferred
+/// unless it is `NoRecords`.
+pub fn try_ips(hostname: &str,
+               timeout: Option<Duration>) -> Result<Vec<IpAddr>, ResolveError> {
+    let mut err = ResolveError::NotFound;
+    let (conf, found) = load_config();
+    for source in hosts::system_sources() {
+        let res = match source {
+            Source::Files => Ok(hosts_ips(hostname)),
+            Source::Dns => dns_ips(&conf, hostname, timeout).map_err(|e| no_config(e, found)),
+        };
+        match res {
+            Ok(mut res) if !res.is_empty() => {
+                sort_ips(&mut res, &policy());
+                conf.apply_sortlist(&mut res);
+                return Ok(res);
+            },
+            Ok(_) => { },
+            Err(e) => err = e,
+        }
+    }
+    Err(err)
+}
+
+/// Returns the addresses of this hostname in `/etc/hosts`.
+pub fn hosts_ips(hostname: &str) -> Vec<IpAddr> {
+    static HOSTS: Mutex<Option<HostsFile>> = Mutex::new(None);
+    let mut hosts = HOSTS.lock().unwrap_or_else(|e| e.into_inner());
+    hosts.get_or_insert_with(|| HostsFile::new("/etc/hosts")).get().addresses(hostname)
+}
+
+/// Queries the nameservers for the A and AAAA records of this hostname.
+///
+/// Both queries are sent at once unless `single_request` is set in `conf`.
+fn dns_ips(conf: &ResolverConfig, hostname: &str,
+           timeout: Option<Duration>) -> Result<Vec<IpAddr>, ResolveError> {
+    let types = [Type::A, Type::Aaaa];
     let mut res = vec!();
-    let mut file = BufReader::new(trycvt!(File::open("/etc/resolv.conf")));
-    let mut line = String::new();
-    while file.read_line(&mut line).is_ok() {
-        if line.len() == 0 {
-            break;
-        }
-        if line.starts_with("nameserver ") {
-            if let Ok(ip) = line["nameserver ".len()..].trim().parse() {
-                res.push(ip);
+    let mut err = None;
+    let (name, responses) = search(conf, hostname, &types, Class::In, timeout)?;
+    for (&(ref packet, server), &ty) in responses.iter().zip(types.iter()) {
+        let data = match answers(packet, server, &name, ty) {
+            Ok(data) => data,
+            Err(e) => {
+                if err.is_none() || matches!(err, Some(ResolveError::NoRecords(..))) {
+                    err = Some(e);
+                }
+                continue;
+            },
+        };
+        for data in data {
+            match data {
+                Data::A(addr) => res.push(IpAddr::V4(addr)),
+                Data::Aaaa(addr) => res.push(IpAddr::V6(addr)),
+                _ => { },
             }
         }
-        line.truncate(0);
     }
-    Ok(res)
+    match err {
+        Some(e) if res.is_empty() => Err(e),
+        _ => Ok(res),
+    }
 }
 
-/// Retrieves a list of nameservers from the OS.
-pub fn nameservers() -> Vec<IpAddr> {
-    match nameservers_int() {
-        Ok(v) => v,
-        _ => vec!(),
+/// Returns the address selection policy of the system from `/etc/gai.conf`.
+pub fn policy() -> Policy {
+    match fs::read_to_string("/etc/gai.conf") {
+        Ok(s) => Policy::parse_gai_conf(&s),
+        _ => Policy::default(),
     }
 }
 
-/// Queries a nameserver for the A and AAAA records of this hostname.
-pub fn ips(hostname: &str, timeout: Option<Duration>) -> Vec<IpAddr> {
-    let mut res = match get_ips_int(hostname, timeout, Type::A) {
-        Ok(v) => v,
-        _ => vec!(),
+/// Sorts addresses by preference according to RFC 6724.
+///
+/// The source address of each destination is the address the OS would choose for it.
+/// Destinations the OS has no route to come last.
+pub fn sort_ips(ips: &mut [IpAddr], policy: &Policy) {
+    let mut dsts: Vec<_> = ips.iter().map(|&ip| (ip, source_ip(ip))).collect();
+    selection::sort(&mut dsts, policy);
+    for (ip, dst) in ips.iter_mut().zip(dsts) {
+        *ip = dst.0;
+    }
+}
+
+/// Returns the source address the OS would use to send packets to `ip`.
+fn source_ip(ip: IpAddr) -> Option<IpAddr> {
+    // Connecting a UDP socket chooses the source address without sending anything.
+    let socket = match ip {
+        IpAddr::V4(..) => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)),
+        IpAddr::V6(..) => UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)),
+    };
+    let socket = socket.ok()?;
+    socket.connect((ip, 9)).ok()?;
+    socket.local_addr().ok().map(|a| a.ip())
+}
+
+/// Returns the data of the answers for `name` from a response of `server`.
+fn answers(packet: &Packet, server: Option<SocketAddr>, name: &str,
+           ty: Type) -> Result<Vec<Data>, ResolveError> {
+    let res: Vec<_> = packet.answers_for(name, ty).into_iter().map(|r| r.data.clone())
+                            .collect();
+    let response = ErrorResponse {
+        response_code: packet.response_code,
+        server:        server,
+        soa:           packet.authority.iter().find(|r| r.data.to_type() == Type::Soa)
+                                     .map(|r| Box::new(r.clone())),
     };
-    if let Ok(v) = get_ips_int(hostname, timeout, Type::Aaaa) {
-        res.extend(v.into_iter());
+    match packet.response_code {
+        ResponseCode::Ok if !res.is_empty() => Ok(res),
+        ResponseCode::Ok => Err(ResolveError::NoRecords(response)),
+        ResponseCode::NameError => Err(ResolveError::NameError(response)),
+        _ => Err(ResolveError::ServerError(response)),
     }
-    res
 }
 
-fn query_int(hostname: &str, ty: Type,
-                 timeout: Option<Duration>) -> Result<Vec<Data>, ()> {
-    let mut socket = trycvt!(get_socket());
-    if let Some(t) = timeout {
-        try!(set_timeout(&mut socket, t));
+/// Looks up `hostname` with the search list of the configuration.
+///
+/// The candidates of `ResolverConfig::candidates` are queried for all `types` in order
+/// until one of them has records of one of the types. Names that do not exist, have no
+/// such records, or whose nameserver failed are skipped. Any other response and errors
+/// such as timeouts end the search.
+///
+/// Returns the name that was queried last together with the responses and the servers
+/// that sent them in the order of `types`. If no candidate has records, the first
+/// responses without data are preferred over the last failure.
+fn search(conf: &ResolverConfig, hostname: &str, types: &[Type], class: Class,
+          timeout: Option<Duration>) -> Result<(String, Vec<Response>), ResolveError> {
+    let mut nodata = None;
+    let mut last = None;
+    for name in conf.candidates(hostname) {
+        let packets = exchange(conf, &name, types, class, timeout)?;
+        let has_data = packets.iter().map(|p| &p.0).zip(types).any(|(p, &ty)| {
+            p.response_code == ResponseCode::Ok && !p.answers_for(&name, ty).is_empty()
+        });
+        if has_data {
+            return Ok((name, packets));
+        }
+        if packets.iter().any(|p| p.0.response_code == ResponseCode::Ok) {
+            if nodata.is_none() {
+                nodata = Some((name, packets));
+            }
+            continue;
+        }
+        let skip = packets.iter().all(|p| {
+            matches!(p.0.response_code, ResponseCode::NameError | ResponseCode::ServerFailure)
+        });
+        if !skip {
+            return Ok((name, packets));
+        }
+        last = Some((name, packets));
     }
-    let id = 12345;
+    nodata.or(last).ok_or(ResolveError::Timeout)
+}
+
+/// A response and the nameserver that sent it, or `None` if it came from the cache.
+type Response = (Packet, Option<SocketAddr>);
+
+/// Returns the cache of the responses of the nameservers.
+///
+/// Every lookup except `chaos_txt` and `server_identity` consults the cache first.
+pub fn cache() -> &'static Cache {
+    static CACHE: OnceLock<Cache> = OnceLock::new();
+    CACHE.get_or_init(|| Cache::new(CacheConfig::default()))
+}
+
+/// Smoothed round-trip times of the nameservers that have been queried.
+static SRTT: Mutex<Vec<(SocketAddr, Duration)>> = Mutex::new(Vec::new());
+
+/// Index of the nameserver that is tried first by the next query if `rotate` is set.
+static ROTATE: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the nameservers in the order in which they are tried.
+///
+/// With `rotate`, the first nameserver changes with every query. Otherwise the
+/// nameservers are ordered by their smoothed round-trip time. Nameservers that have not
+/// been queried yet come first, in the order of the configuration.
+fn ordered_servers(conf: &ResolverConfig) -> Vec<SocketAddr> {
+    let mut servers = conf.servers();
+    if conf.rotate {
+        let first = ROTATE.fetch_add(1, atomic::Ordering::Relaxed) % servers.len();
+        servers.rotate_left(first);
+        return servers;
+    }
+    let mut srtt = SRTT.lock().unwrap_or_else(|e| e.into_inner());
+    servers.sort_by_key(|s| srtt.iter().find(|e| e.0 == *s).map(|e| e.1));
+    // Nameservers that are not preferred slowly lose their penalty so that a nameserver
+    // that failed is eventually tried again.
+    for e in srtt.iter_mut() {
+        if e.0 != servers[0] {
+            e.1 = e.1 * 49 / 50;
+        }
+    }
+    servers
+}
+
+/// Updates the smoothed round-trip time of a nameserver with a new sample.
+fn update_srtt(server: SocketAddr, rtt: Duration) {
+    let mut srtt = SRTT.lock().unwrap_or_else(|e| e.into_inner());
+    match srtt.iter_mut().find(|e| e.0 == server) {
+        Some(e) => e.1 = (e.1 * 7 + rtt) / 8,
+        None => srtt.push((server, rtt)),
+    }
+}
+
+/// Penalizes a nameserver that did not answer within `timeout` or failed.
+fn penalize(server: SocketAddr, timeout: Duration) {
+    let mut srtt = SRTT.lock().unwrap_or_else(|e| e.into_inner());
+    match srtt.iter_mut().find(|e| e.0 == server) {
+        Some(e) => e.1 = (e.1 * 2).max(timeout),
+        None => srtt.push((server, timeout)),
+    }
+}
+
+/// Sends queries for all `types` to the nameservers of the configuration.
+///
+/// All nameservers are tried in the order of `ordered_servers` and the whole list is
+/// tried `attempts` times. The first try of each nameserver waits for `timeout` or the
+/// timeout of the configuration. Like in the C library, later tries wait for twice as
+/// long as the previous round, divided by the number of nameservers, but for at least a
+/// second.
+///
+/// The next nameserver is tried if a nameserver does not answer all queries in time or
+/// responds to one of them with `ServerFailure`, `Refused`, `NotImplemented` or
+/// `FormatError`. If no nameserver gives different responses, the last responses are
+/// returned.
+///
+/// Types whose answer is in `cache` are not queried. Successful responses are stored
+/// in it.
+fn exchange(conf: &ResolverConfig, name: &str, types: &[Type], class: Class,
+            timeout: Option<Duration>) -> Result<Vec<Response>, ResolveError> {
+    let mut cached = vec!();
+    let mut missing = vec!();
+    for &ty in types {
+        let mut query = Packet::query(0);
+        query.question.push(Question {
+            name: name.to_string(),
+            ty: ty,
+            class: class,
+        });
+        let response = cache().lookup(&query);
+        if response.is_none() {
+            missing.push(ty);
+        }
+        cached.push(response.map(|p| (p, None)));
+    }
+    if missing.is_empty() {
+        return Ok(cached.into_iter().flatten().collect());
+    }
+    let (server, packets) = exchange_servers(conf, name, &missing, class, timeout)
+        .map_err(|_| ResolveError::Timeout)?;
+    let mut packets = packets.into_iter().map(|p| (p, Some(server)));
+    Ok(cached.into_iter().map(|c| c.or_else(|| packets.next()).unwrap()).collect())
+}
+
+fn exchange_servers(conf: &ResolverConfig, name: &str, types: &[Type], class: Class,
+                    timeout: Option<Duration>) -> Result<(SocketAddr, Vec<Packet>), ()> {
+    let servers = ordered_servers(conf);
+    let base = timeout.unwrap_or(conf.timeout);
+    let mut last = None;
+    for attempt in 0..conf.attempts.max(1) as u32 {
+        let timeout = match attempt {
+            0 => base,
+            _ => (base * (1 << attempt) / servers.len() as u32).max(Duration::from_secs(1)),
+        };
+        for &server in &servers {
+            let start = Instant::now();
+            let packets = match query_server(conf, server, name, types, class, Some(timeout)) {
+                Ok(p) => p,
+                _ => {
+                    penalize(server, timeout);
+                    continue;
+                },
+            };
+            let failed = packets.iter().any(|p| {
+                matches!(p.response_code, ResponseCode::ServerFailure | ResponseCode::Refused
+                         | ResponseCode::NotImplemented | ResponseCode::FormatError)
+            });
+            if failed {
+                penalize(server, timeout);
+                last = Some((server, packets));
+            } else {
+                update_srtt(server, start.elapsed());
+                for packet in &packets {
+                    cache().insert(packet);
+                }
+                return Ok((server, packets));
+            }
+        }
+    }
+    last.ok_or(())
+}
+
+/// Appends a dot to a name if it does not already end in one so that it is looked up
+/// without the search list.
+fn absolute(name: &str) -> String {
+    if name.ends_with('.') {
+        name.to_string()
+    } else {
+        format!("{}.", name)
+    }
+}
+
+/// Sends queries for all `types` to a nameserver and returns the responses in the same
+/// order.
+///
+/// The queries are sent over UDP unless `use_vc` is set. If a UDP response is truncated,
+/// its query is repeated over TCP. If that fails, the truncated response is returned and
+/// the responses to the other queries are kept. Each transport has `timeout` to complete
+/// the exchange. The AD bit of the responses is cleared unless `trust_ad` is set.
+fn query_server(conf: &ResolverConfig, nameserver: SocketAddr, hostname: &str,
+                types: &[Type], class: Class,
+                timeout: Option<Duration>) -> Result<Vec<Packet>, ()> {
+    let timeout = timeout.unwrap_or(conf.timeout);
+    let mut responses = if conf.use_vc {
+        types.iter().map(|&ty| {
+            query_tcp(conf, nameserver, hostname, ty, class, Instant::now() + timeout)
+        }).collect::<Result<Vec<_>, ()>>()?
+    } else {
+        let deadline = Instant::now() + timeout;
+        let mut responses = query_udp(conf, nameserver, hostname, types, class, deadline)?;
+        for (response, &ty) in responses.iter_mut().zip(types) {
+            if response.truncated {
+                let deadline = Instant::now() + timeout;
+                if let Ok(r) = query_tcp(conf, nameserver, hostname, ty, class, deadline) {
+                    *response = r;
+                }
+            }
+        }
+        responses
+    };
+    if !conf.trust_ad {
+        for response in &mut responses {
+            response.authentic_data = false;
+        }
+    }
+    Ok(responses)
+}
+
+/// Creates a query for `hostname` with a random ID.
+fn new_query(conf: &ResolverConfig, hostname: &str, ty: Type,
+             class: Class) -> Result<Packet, ()> {
+    let id = random::u16()? as i16;
+    let name = if conf.randomize_case {
+        randomize_case(hostname)?
+    } else {
+        hostname.to_string()
+    };
     let mut packet = Packet::query(id);
     packet.question.push(Question {
-        name: hostname.to_string(),
+        name: name,
         ty: ty,
-        class: Class::In
+        class: class,
     });
-    let mut buf = [0; 512];
-    let len = trycvt!(packet.format(&mut buf));
-    let nameserver = nameservers().into_iter().next().unwrap_or(IpAddr::new_v4(8,8,8,8));
-    trycvt!(socket.send_to(&buf[..len], &(nameserver, 53)));
-    let len = trycvt!(socket.recv_from(&mut buf)).0;
-    let packet = trycvt!(Packet::parse(&buf[..len])).1;
-    Ok(packet.answer.into_iter().map(|ans|ans.data).collect())
+    packet.authentic_data = conf.trust_ad;
+    if conf.edns0 {
+        packet.edns = Some(Edns {
+            udp_payload_size: DEFAULT_UDP_PAYLOAD_SIZE,
+            extended_rcode:   0,
+            version:          0,
+            dnssec_ok:        false,
+            options:          vec!(),
+        });
+    }
+    Ok(packet)
 }
 
-/// Queries a nameserver for the data with type `ty`.
+/// Sends queries for all `types` over one UDP socket.
+///
+/// The queries are sent at once unless `single_request` is set, in which case each query
+/// is sent after the response to the previous one has arrived.
+fn query_udp(conf: &ResolverConfig, nameserver: SocketAddr, hostname: &str, types: &[Type],
+             class: Class, deadline: Instant) -> Result<Vec<Packet>, ()> {
+    let socket = trycvt!(get_socket(nameserver));
+    let mut queries = vec!();
+    for &ty in types {
+        queries.push(new_query(conf, hostname, ty, class)?);
+    }
+    let mut responses = vec!(None; queries.len());
+    let batch = if conf.single_request { 1 } else { queries.len() };
+    let mut buf = [0; DEFAULT_UDP_PAYLOAD_SIZE as usize];
+    let mut sent = 0;
+    while sent < queries.len() {
+        let end = (sent + batch).min(queries.len());
+        for query in &queries[sent..end] {
+            let len = trycvt!(query.format(&mut buf));
+            trycvt!(socket.send_to(&buf[..len], nameserver));
+        }
+        sent = end;
+        // Datagrams that are not a response to a query are ignored until the deadline.
+        while responses[..sent].iter().any(Option::is_none) {
+            trycvt!(socket.set_read_timeout(Some(remaining(deadline)?)));
+            let (len, from) = trycvt!(socket.recv_from(&mut buf));
+            if from.ip() != nameserver.ip() || from.port() != nameserver.port() {
+                continue;
+            }
+            let response = match Packet::parse(&buf[..len]) {
+                Ok((_, r)) => r,
+                _ => continue,
+            };
+            let pos = (0..sent).find(|&i| {
+                responses[i].is_none()
+                    && is_response_to(&queries[i], &response, conf.randomize_case)
+            });
+            if let Some(i) = pos {
+                responses[i] = Some(response);
+            }
+        }
+    }
+    Ok(responses.into_iter().flatten().collect())
+}
+
+/// Sends a query over TCP. Messages are prefixed with their length (RFC 1035 section
+/// 4.2.2).
+fn query_tcp(conf: &ResolverConfig, nameserver: SocketAddr, hostname: &str, ty: Type,
+             class: Class, deadline: Instant) -> Result<Packet, ()> {
+    let stream = trycvt!(TcpStream::connect_timeout(&nameserver, remaining(deadline)?));
+    let packet = new_query(conf, hostname, ty, class)?;
+    let mut buf = vec!(0; 2 + DEFAULT_UDP_PAYLOAD_SIZE as usize);
+    let len = trycvt!(packet.format(&mut buf[2..]));
+    buf[..2].copy_from_slice(&(len as u16).to_be_bytes());
+    trycvt!(stream.set_write_timeout(Some(remaining(deadline)?)));
+    trycvt!((&stream).write_all(&buf[..2 + len]));
+    let mut prefix = [0; 2];
+    read_exact_until(&stream, &mut prefix, deadline)?;
+    let mut buf = vec!(0; u16::from_be_bytes(prefix) as usize);
+    read_exact_until(&stream, &mut buf, deadline)?;
+    let response = Packet::parse(&buf)?.1;
+    if !is_response_to(&packet, &response, conf.randomize_case) {
+        return Err(());
+    }
+    Ok(response)
+}
+
+/// Returns the time until `deadline` or an error if it has passed.
+fn remaining(deadline: Instant) -> Result<Duration, ()> {
+    match deadline.checked_duration_since(Instant::now()) {
+        Some(d) if d > Duration::from_secs(0) => Ok(d),
+        _ => Err(()),
+    }
+}
+
+fn read_exact_until(mut stream: &TcpStream, buf: &mut [u8],
+                    deadline: Instant) -> Result<(), ()> {
+    let mut pos = 0;
+    while pos < buf.len() {
+        trycvt!(stream.set_read_timeout(Some(remaining(deadline)?)));
+        match stream.read(&mut buf[pos..]) {
+            Ok(0) => return Err(()),
+            Ok(n) => pos += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => { },
+            Err(_) => return Err(()),
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether `response` answers `query`: the ID and the question section must be
+/// the same. Names are compared without regard to case unless `exact_case` is set.
+fn is_response_to(query: &Packet, response: &Packet, exact_case: bool) -> bool {
+    if response.is_query || response.id != query.id {
+        return false;
+    }
+    if response.question.len() != query.question.len() {
+        return false;
+    }
+    query.question.iter().zip(response.question.iter()).all(|(q, r)| {
+        let name = if exact_case {
+            q.name == r.name
+        } else {
+            q.name.eq_ignore_ascii_case(&r.name)
+        };
+        name && q.ty == r.ty && q.class == r.class
+    })
+}
+
+/// Queries a nameserver for the PTR records of this address.
+///
+/// If `confirm` is set, only names whose A or AAAA records contain the address are
+/// returned (forward-confirmed reverse DNS).
+pub fn reverse(ip: IpAddr, confirm: bool, timeout: Option<Duration>) -> Vec<String> {
+    let mut res = vec!();
+    for data in query(&absolute(&rev::name(ip)), Type::Ptr, timeout) {
+        if let Data::Ptr(name) = data {
+            res.push(name);
+        }
+    }
+    if confirm {
+        let ty = match ip {
+            IpAddr::V4(..) => Type::A,
+            IpAddr::V6(..) => Type::Aaaa,
+        };
+        res.retain(|name| {
+            let addrs = get_ips_int(&absolute(name), timeout, ty);
+            addrs.map(|addrs| addrs.contains(&ip)).unwrap_or(false)
+        });
+    }
+    res
+}
+
+/// Queries a nameserver for the data with type `ty` or returns an empty vector if the
+/// lookup fails.
+///
+/// See `try_query`.
 pub fn query(hostname: &str, ty: Type, timeout: Option<Duration>) -> Vec<Data> {
-    match query_int(hostname, ty, timeout) {
-        Ok(v) => v,
-        _ => vec!(),
+    try_query(hostname, ty, timeout).unwrap_or_default()
+}
+
+/// Queries a nameserver for the data with type `ty`.
+///
+/// Names are qualified with the search list like in `ips`. Failed nameservers are
+/// retried and other nameservers are tried as described in `ResolverConfig`. `timeout`
+/// is the time to wait for the first response of each nameserver. If it is `None`, the
+/// timeout from the resolver configuration is used.
+pub fn try_query(hostname: &str, ty: Type,
+                 timeout: Option<Duration>) -> Result<Vec<Data>, ResolveError> {
+    let (conf, found) = load_config();
+    let (name, responses) = search(&conf, hostname, &[ty], Class::In, timeout)
+        .map_err(|e| no_config(e, found))?;
+    let (ref packet, server) = responses[0];
+    answers(packet, server, &name, ty)
+}
+
+/// Options of `connect_with`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ConnectOptions {
+    /// Time to wait for the AAAA records after the A records have arrived.
+    pub resolution_delay: Duration,
+    /// Time to wait for a connection attempt before the next one is started.
+    pub attempt_delay:    Duration,
+    /// Timeout of the DNS queries as in `query`.
+    pub dns_timeout:      Option<Duration>,
+    /// Time after which `connect_with` gives up.
+    pub timeout:          Duration,
+}
+
+impl Default for ConnectOptions {
+    /// Returns the delays recommended by RFC 8305 and a timeout of 30 seconds.
+    fn default() -> ConnectOptions {
+        ConnectOptions {
+            resolution_delay: Duration::from_millis(50),
+            attempt_delay:    Duration::from_millis(250),
+            dns_timeout:      None,
+            timeout:          Duration::from_secs(30),
+        }
+    }
+}
+
+enum Event {
+    Resolved(Type, Vec<IpAddr>),
+    Connected(TcpStream),
+    Failed(io::Error),
+}
+
+/// Connects to a port of a host with the default options.
+///
+/// See `connect_with`.
+pub fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    connect_with(host, port, &ConnectOptions::default())
+}
+
+/// Connects to a port of a host with the Happy Eyeballs algorithm (RFC 8305).
+///
+/// The A and AAAA records are looked up at the same time, each like with `ips`.
+/// Connection attempts start as soon as the AAAA records have arrived, or
+/// `resolution_delay` after the A records if the AAAA records are still missing. The
+/// addresses are sorted with `sort_ips` and the sortlist of the resolver configuration
+/// like in `try_ips`, and then the families are interleaved. A new attempt
+/// is started whenever the previous attempt fails or has not succeeded within
+/// `attempt_delay`. The first connection that succeeds is returned.
+///
+/// Returns the error of the last attempt if all attempts fail, or an error of kind
+/// `NotFound` if the host has no addresses.
+pub fn connect_with(host: &str, port: u16, options: &ConnectOptions) -> io::Result<TcpStream> {
+    let start = Instant::now();
+    let deadline = start + options.timeout;
+    let (tx, rx) = mpsc::channel();
+    match host.parse::<IpAddr>() {
+        Ok(ip) => {
+            let (ty, other) = match ip {
+                IpAddr::V4(..) => (Type::A, Type::Aaaa),
+                IpAddr::V6(..) => (Type::Aaaa, Type::A),
+            };
+            let _ = tx.send(Event::Resolved(ty, vec!(ip)));
+            let _ = tx.send(Event::Resolved(other, vec!()));
+        },
+        _ => {
+            for &ty in &[Type::Aaaa, Type::A] {
+                let tx = tx.clone();
+                let host = host.to_string();
+                let timeout = options.dns_timeout;
+                thread::spawn(move || {
+                    let _ = tx.send(Event::Resolved(ty, family_ips(&host, ty, timeout)));
+                });
+            }
+        },
+    }
+    let policy = policy();
+    let conf = config();
+    let mut v4_at = None;
+    let mut v6_done = false;
+    let mut pending: Vec<IpAddr> = vec!();
+    let mut in_flight = 0;
+    let mut next_attempt = start;
+    let mut last_err = None;
+    loop {
+        let now = Instant::now();
+        let ready = v6_done || v4_at.map(|t| now >= t + options.resolution_delay) == Some(true);
+        if ready && !pending.is_empty() && now >= next_attempt {
+            let addr = SocketAddr::from((pending.remove(0), port));
+            let tx = tx.clone();
+            let timeout = deadline.saturating_duration_since(now);
+            thread::spawn(move || {
+                let _ = match TcpStream::connect_timeout(&addr, timeout) {
+                    Ok(s) => tx.send(Event::Connected(s)),
+                    Err(e) => tx.send(Event::Failed(e)),
+                };
+            });
+            in_flight += 1;
+            next_attempt = now + options.attempt_delay;
+            continue;
+        }
+        if v6_done && v4_at.is_some() && pending.is_empty() && in_flight == 0 {
+            return Err(last_err.unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "no addresses")
+            }));
+        }
+        let mut wake = deadline;
+        if !ready {
+            if let Some(t) = v4_at {
+                wake = wake.min(t + options.resolution_delay);
+            }
+        } else if !pending.is_empty() {
+            wake = wake.min(next_attempt);
+        }
+        let event = match rx.recv_timeout(wake.saturating_duration_since(now)) {
+            Ok(e) => e,
+            Err(_) if Instant::now() < deadline => continue,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::TimedOut, "connect timed out")),
+        };
+        match event {
+            Event::Resolved(ty, ips) => {
+                if ty == Type::Aaaa {
+                    v6_done = true;
+                } else {
+                    v4_at = Some(Instant::now());
+                }
+                pending.extend(ips);
+                sort_ips(&mut pending, &policy);
+                conf.apply_sortlist(&mut pending);
+                pending = interleave(&pending);
+            },
+            Event::Connected(stream) => return Ok(stream),
+            Event::Failed(e) => {
+                in_flight -= 1;
+                last_err = Some(e);
+                next_attempt = Instant::now();
+            },
+        }
+    }
+}
+
+/// Returns the addresses of one family of a host like `ips`, but unsorted.
+fn family_ips(host: &str, ty: Type, timeout: Option<Duration>) -> Vec<IpAddr> {
+    for source in hosts::system_sources() {
+        let res = match source {
+            Source::Files => {
+                let mut res = hosts_ips(host);
+                res.retain(|ip| ip.is_ipv6() == (ty == Type::Aaaa));
+                res
+            },
+            Source::Dns => get_ips_int(host, timeout, ty).unwrap_or_default(),
+        };
+        if !res.is_empty() {
+            return res;
+        }
+    }
+    vec!()
+}
+
+/// Interleaves the addresses of both families, starting with the family of the first
+/// address. The order within each family is kept (RFC 8305 section 4).
+fn interleave(ips: &[IpAddr]) -> Vec<IpAddr> {
+    let first_v6 = match ips.first() {
+        Some(ip) => ip.is_ipv6(),
+        _ => return vec!(),
+    };
+    let mut first = ips.iter().filter(|ip| ip.is_ipv6() == first_v6);
+    let mut second = ips.iter().filter(|ip| ip.is_ipv6() != first_v6);
+    let mut res = vec!();
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => return res,
+            (a, b) => res.extend(a.into_iter().chain(b).cloned()),
+        }
+    }
+}
+
+/// A server identity that can be queried in the CHAOS class.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Identity {
+    /// `version.bind`
+    VersionBind,
+    /// `hostname.bind`
+    HostnameBind,
+    /// `id.server` (RFC 4892)
+    IdServer,
+    /// `version.server`
+    VersionServer,
+}
+
+impl Identity {
+    /// Returns the name that is queried.
+    pub fn name(self) -> &'static str {
+        match self {
+            Identity::VersionBind   => "version.bind",
+            Identity::HostnameBind  => "hostname.bind",
+            Identity::IdServer      => "id.server",
+            Identity::VersionServer => "version.server",
+        }
+    }
+}
+
+/// Queries `server` for the CHAOS TXT records of `name`.
+///
+/// Returns the character-strings of all TXT records in the answer.
+pub fn chaos_txt(server: IpAddr, name: &str, timeout: Option<Duration>) -> Vec<Vec<u8>> {
+    let mut res = vec!();
+    let server = SocketAddr::from((server, 53));
+    let packets = query_server(&config(), server, name, &[Type::Txt], Class::Ch, timeout);
+    if let Ok(v) = packets.map_err(|_| ResolveError::Timeout)
+                          .and_then(|p| answers(&p[0], Some(server), name, Type::Txt)) {
+        for data in v {
+            if let Data::Txt(strings) = data {
+                res.extend(strings);
+            }
+        }
+    }
+    res
+}
+
+/// Queries `server` for its identity, e.g., its software version.
+pub fn server_identity(server: IpAddr, identity: Identity,
+                       timeout: Option<Duration>) -> Vec<Vec<u8>> {
+    chaos_txt(server, identity.name(), timeout)
+}
+
+/// Performs the lookups of an SPF evaluation with the toy API.
+///
+/// Server failures and timeouts are reported as errors, i.e., as `temperror`.
+pub struct SpfLookup {
+    /// Timeout of each query.
+    pub timeout: Option<Duration>,
+}
+
+impl spf::Lookup for SpfLookup {
+    fn lookup(&mut self, name: &str, ty: Type) -> Result<Vec<Data>, ()> {
+        let res = match try_query(&absolute(name), ty, self.timeout) {
+            Ok(res) => res,
+            Err(ResolveError::NameError(..)) | Err(ResolveError::NoRecords(..)) => vec!(),
+            Err(_) => return Err(()),
+        };
+        Ok(res.into_iter().filter(|d| d.to_type() == ty).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener};
+
+    /// Answers a query with an address that tells whether it was sent over TCP.
+    fn respond(query: &Packet, tcp: bool, truncate: bool) -> Packet {
+        let q = &query.question[0];
+        let data = match (q.ty, tcp) {
+            (Type::A, false) => Data::A(Ipv4Addr::new(192, 0, 2, 1)),
+            (Type::A, true)  => Data::A(Ipv4Addr::new(192, 0, 2, 2)),
+            (_, false)       => Data::Aaaa(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            (_, true)        => Data::Aaaa(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2)),
+        };
+        let response = Packet::response_to(query).truncated(truncate);
+        if truncate {
+            return response.build();
+        }
+        response.answer(Record {
+            name:         q.name.clone(),
+            class:        q.class,
+            time_to_live: Duration::from_secs(60),
+            data:         data,
+        }).build()
+    }
+
+    /// Starts a nameserver on the loopback address. UDP responses to queries of type
+    /// `truncate` are truncated. TCP connections are refused unless `tcp` is set.
+    fn serve(truncate: Option<Type>, tcp: bool) -> SocketAddr {
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = udp.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            loop {
+                let (len, from) = udp.recv_from(&mut buf).unwrap();
+                let query = Packet::parse(&buf[..len]).unwrap().1;
+                let truncate = Some(query.question[0].ty) == truncate;
+                let len = respond(&query, false, truncate).format(&mut buf).unwrap();
+                udp.send_to(&buf[..len], from).unwrap();
+            }
+        });
+        if tcp {
+            let listener = TcpListener::bind(addr).unwrap();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    let mut buf = [0; 514];
+                    stream.read_exact(&mut buf[..2]).unwrap();
+                    let len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+                    stream.read_exact(&mut buf[..len]).unwrap();
+                    let query = Packet::parse(&buf[..len]).unwrap().1;
+                    let len = respond(&query, true, false).format(&mut buf[2..]).unwrap();
+                    buf[..2].copy_from_slice(&(len as u16).to_be_bytes());
+                    stream.write_all(&buf[..2 + len]).unwrap();
+                }
+            });
+        }
+        addr
+    }
+
+    fn data(responses: &[Packet]) -> Vec<Vec<Data>> {
+        responses.iter().map(|p| p.answer.iter().map(|r| r.data.clone()).collect()).collect()
+    }
+
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(2));
+
+    #[test]
+    fn truncated_over_tcp() {
+        let server = serve(Some(Type::A), true);
+        let conf = ResolverConfig::default();
+        let responses = query_server(&conf, server, "example.com", &[Type::A, Type::Aaaa],
+                                     Class::In, TIMEOUT).unwrap();
+        assert!(responses.iter().all(|p| !p.truncated));
+        assert_eq!(data(&responses), [
+            vec!(Data::A(Ipv4Addr::new(192, 0, 2, 2))),
+            vec!(Data::Aaaa(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1))),
+        ]);
+    }
+
+    /// A failed TCP retry keeps the truncated response and the other responses.
+    #[test]
+    fn truncated_without_tcp() {
+        let server = serve(Some(Type::A), false);
+        let conf = ResolverConfig::default();
+        let responses = query_server(&conf, server, "example.com", &[Type::A, Type::Aaaa],
+                                     Class::In, TIMEOUT).unwrap();
+        assert!(responses[0].truncated);
+        assert_eq!(data(&responses), [
+            vec!(),
+            vec!(Data::Aaaa(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1))),
+        ]);
+    }
+
+    #[test]
+    fn use_vc() {
+        let server = serve(None, true);
+        let conf = ResolverConfig {
+            use_vc: true,
+            ..ResolverConfig::default()
+        };
+        let responses = query_server(&conf, server, "example.com", &[Type::A, Type::Aaaa],
+                                     Class::In, TIMEOUT).unwrap();
+        assert_eq!(data(&responses), [
+            vec!(Data::A(Ipv4Addr::new(192, 0, 2, 2))),
+            vec!(Data::Aaaa(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2))),
+        ]);
+    }
+
+    fn parse_ips(ips: &[&str]) -> Vec<IpAddr> {
+        ips.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn interleave_families() {
+        let ips = parse_ips(&["::1", "::2", "::3", "10.0.0.1", "10.0.0.2"]);
+        assert_eq!(interleave(&ips), parse_ips(&["::1", "10.0.0.1", "::2", "10.0.0.2", "::3"]));
+        let ips = parse_ips(&["10.0.0.1", "::1", "::2", "10.0.0.2"]);
+        assert_eq!(interleave(&ips), parse_ips(&["10.0.0.1", "::1", "10.0.0.2", "::2"]));
+        assert!(interleave(&[]).is_empty());
+    }
+
+    /// Address literals are connected to without DNS queries.
+    #[test]
+    fn connect_literal() {
+        let options = ConnectOptions {
+            timeout: Duration::from_secs(2),
+            ..ConnectOptions::default()
+        };
+        for ip in ["127.0.0.1", "::1"] {
+            let listener = match TcpListener::bind((ip, 0)) {
+                Ok(l) => l,
+                // The loopback address of this family is not configured.
+                _ => continue,
+            };
+            let addr = listener.local_addr().unwrap();
+            let stream = connect_with(ip, addr.port(), &options).unwrap();
+            assert_eq!(stream.peer_addr().unwrap(), addr);
+            drop(listener);
+            let err = connect_with(ip, addr.port(), &options).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        }
     }
 }
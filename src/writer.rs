@@ -0,0 +1,500 @@
+use crate::{FormatError, Packet, Question, Record, Edns, format, len};
+use crate::rust::{WriteExt2};
+
+/// A section of a message that contains records.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Section {
+    /// Answers.
+    Answer     = 1,
+    /// Pointers to authorities.
+    Authority  = 2,
+    /// Additional information.
+    Additional = 3,
+}
+
+/// What `MessageWriter` does if a record does not fit into the buffer.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Overflow {
+    /// Remove the records of the incomplete RRset and set the TC bit. All further
+    /// records are dropped. RRsets in the additional section are dropped without setting
+    /// the TC bit. The OPT record is still appended (see `MessageWriter::edns`).
+    Truncate,
+    /// Return `FormatError::Buffer`. The message contains everything written so far.
+    Error,
+}
+
+/// The start of the RRset that is currently being written.
+#[derive(Copy, Clone)]
+struct RRsetStart {
+    /// Position of the first record of the RRset.
+    pos:     usize,
+    /// Section of the RRset.
+    section: usize,
+    /// Type and class of the RRset.
+    ty:      u16,
+    class:   u16,
+}
+
+/// Writes a message into a buffer one question or record at a time.
+///
+/// The section counts in the header are kept up to date, so the first `len()` bytes of
+/// the buffer are always a complete message. Questions and records have to be added in
+/// the order of their sections.
+pub struct MessageWriter<'a> {
+    buf:       &'a mut [u8],
+    pos:       usize,
+    section:   usize,
+    counts:    [u16; 4],
+    overflow:  Overflow,
+    rrset:     Option<RRsetStart>,
+    truncated: bool,
+}
+
+impl<'a> MessageWriter<'a> {
+    /// Creates a new writer and writes the header.
+    ///
+    /// The header fields are taken from `header`. Its questions and records are
+    /// ignored.
+    pub fn new(buf: &'a mut [u8], header: &Packet,
+               overflow: Overflow) -> Result<MessageWriter<'a>, FormatError> {
+        if buf.len() < 12 {
+            return Err(FormatError::Buffer(12));
+        }
+        {
+            let mut dst = &mut buf[..];
+            format::header(&mut dst, header);
+        }
+        for b in buf[4..12].iter_mut() {
+            *b = 0;
+        }
+        Ok(MessageWriter {
+            buf:       buf,
+            pos:       12,
+            section:   0,
+            counts:    [0; 4],
+            overflow:  overflow,
+            rrset:     None,
+            truncated: false,
+        })
+    }
+
+    /// Appends a question.
+    pub fn question(&mut self, q: &Question) -> Result<(), FormatError> {
+        self.question_raw(&q.name, q.ty as u16, q.class as u16)
+    }
+
+    /// Appends a question without requiring a `Question`.
+    pub fn question_raw(&mut self, name: &str, ty: u16, class: u16) -> Result<(), FormatError> {
+        if self.section > 0 {
+            return Err(FormatError::Section);
+        }
+        let len = len::question_raw(name);
+        if self.pos + len > self.buf.len() {
+            // Questions are never truncated.
+            return Err(FormatError::Buffer(self.pos + len));
+        }
+        self.check_count(0)?;
+        {
+            let mut dst = &mut self.buf[self.pos..];
+            format::question_raw(&mut dst, name, ty, class)?;
+        }
+        self.pos += len;
+        self.increment(0);
+        Ok(())
+    }
+
+    /// Appends a record to a section.
+    ///
+    /// Consecutive records with the same owner, type and class in the same section form
+    /// an RRset.
+    pub fn record(&mut self, section: Section, r: &Record) -> Result<(), FormatError> {
+        if len::data(&r.data) > 0xffff {
+            return Err(FormatError::Size);
+        }
+        let len = len::record(r);
+        self.append(section, &r.name, r.data.to_type() as u16, r.class as u16, len,
+                    |dst| format::record(dst, r))
+    }
+
+    /// Appends a record whose data is already in wire format.
+    ///
+    /// Unlike `record`, this does not require a `Record` and therefore works without
+    /// allocating. `rdata` must not contain compressed names.
+    pub fn record_raw(&mut self, section: Section, name: &str, ty: u16, class: u16,
+                      ttl: u32, rdata: &[u8]) -> Result<(), FormatError> {
+        if rdata.len() > 0xffff {
+            return Err(FormatError::Size);
+        }
+        let len = len::record_raw(name, rdata);
+        self.append(section, name, ty, class, len,
+                    |dst| format::record_raw(dst, name, ty, class, ttl, rdata))
+    }
+
+    fn append<F>(&mut self, section: Section, name: &str, ty: u16, class: u16, len: usize,
+                 write: F) -> Result<(), FormatError>
+        where F: FnOnce(&mut &mut [u8]) -> Result<(), FormatError>,
+    {
+        let section = section as usize;
+        if section < self.section {
+            return Err(FormatError::Section);
+        }
+        if self.truncated {
+            return Ok(());
+        }
+        self.section = section;
+
+        let same_rrset = match self.rrset {
+            Some(ref s) => {
+                s.section == section && s.ty == ty && s.class == class
+                    && wire_name_eq(&self.buf[s.pos..], name)
+            },
+            _ => false,
+        };
+
+        if self.pos + len > self.buf.len() {
+            return self.overflow(section, same_rrset, len);
+        }
+        self.check_count(section)?;
+        {
+            let mut dst = &mut self.buf[self.pos..];
+            write(&mut dst)?;
+        }
+        if !same_rrset {
+            self.rrset = Some(RRsetStart {
+                pos:     self.pos,
+                section: section,
+                ty:      ty,
+                class:   class,
+            });
+        }
+        self.pos += len;
+        self.increment(section);
+        Ok(())
+    }
+
+    /// Appends the OPT pseudo-record to the additional section.
+    ///
+    /// The OPT record is written even if the message has been truncated. If it does not
+    /// fit, whole RRsets are removed from the end of the message to make room for it as
+    /// described in `Overflow::Truncate`.
+    pub fn edns(&mut self, e: &Edns) -> Result<(), FormatError> {
+        let section = Section::Additional as usize;
+        let len = len::opt(e);
+        if self.pos + len > self.buf.len() {
+            if self.overflow == Overflow::Error {
+                return Err(FormatError::Buffer(self.pos + len));
+            }
+            self.make_room(len)?;
+        }
+        self.check_count(section)?;
+        self.section = section;
+        {
+            let mut dst = &mut self.buf[self.pos..];
+            format::opt(&mut dst, e)?;
+        }
+        self.rrset = None;
+        self.pos += len;
+        self.increment(section);
+        Ok(())
+    }
+
+    fn overflow(&mut self, section: usize, same_rrset: bool,
+                len: usize) -> Result<(), FormatError> {
+        if self.overflow == Overflow::Error {
+            return Err(FormatError::Buffer(self.pos + len));
+        }
+        if same_rrset {
+            // Remove the records of the incomplete RRset.
+            let start = self.rrset.unwrap().pos;
+            let mut removed = 0;
+            let mut pos = start;
+            while pos < self.pos {
+                pos += record_len(&self.buf[pos..self.pos]);
+                removed += 1;
+            }
+            self.pos = start;
+            self.counts[section] -= removed;
+            let count = self.counts[section];
+            self.write_count(section, count);
+        }
+        self.rrset = None;
+        self.truncated = true;
+        if section != Section::Additional as usize {
+            self.buf[2] |= 0b0000_0010;
+        }
+        Ok(())
+    }
+
+    /// Removes whole RRsets from the end of the message until `len` more bytes fit.
+    fn make_room(&mut self, len: usize) -> Result<(), FormatError> {
+        let mut pos = 12;
+        for _ in 0..self.counts[0] {
+            pos += name_len(&self.buf[pos..]) + 4;
+        }
+        // The last position at which an RRset starts and the section counts before it.
+        let mut cut = (pos, [self.counts[0], 0, 0, 0]);
+        let mut counts = cut.1;
+        let mut prev: Option<(usize, usize)> = None;
+        for section in 1..4 {
+            for _ in 0..self.counts[section] {
+                let start = pos + name_len(&self.buf[pos..]);
+                let same_rrset = match prev {
+                    Some((s, p)) => {
+                        let n = name_len(&self.buf[p..]);
+                        s == section && self.buf[p..p + n + 4].eq_ignore_ascii_case(
+                            &self.buf[pos..start + 4])
+                    },
+                    _ => false,
+                };
+                if !same_rrset && pos + len <= self.buf.len() {
+                    cut = (pos, counts);
+                }
+                prev = Some((section, pos));
+                pos += record_len(&self.buf[pos..]);
+                counts[section] += 1;
+            }
+        }
+        let (pos, counts) = cut;
+        if pos + len > self.buf.len() {
+            return Err(FormatError::Buffer(pos + len));
+        }
+        if counts[1..3] != self.counts[1..3] {
+            self.buf[2] |= 0b0000_0010;
+        }
+        self.counts = counts;
+        for (section, &count) in counts.iter().enumerate().skip(1) {
+            self.write_count(section, count);
+        }
+        self.pos = pos;
+        self.rrset = None;
+        self.truncated = true;
+        Ok(())
+    }
+
+    /// Returns whether records have been dropped.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Returns the number of bytes written so far.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.pos
+    }
+
+    /// Finishes the message and returns its length.
+    pub fn finish(self) -> usize {
+        self.pos
+    }
+
+    /// Returns an error if another entry does not fit into the count of a section.
+    fn check_count(&self, section: usize) -> Result<(), FormatError> {
+        match self.counts[section] {
+            u16::MAX => Err(FormatError::Size),
+            _ => Ok(()),
+        }
+    }
+
+    fn increment(&mut self, section: usize) {
+        self.counts[section] += 1;
+        let count = self.counts[section];
+        self.write_count(section, count);
+    }
+
+    fn write_count(&mut self, section: usize, count: u16) {
+        let mut dst = &mut self.buf[4 + 2 * section..];
+        let _ = dst.write_u16_be(count);
+    }
+}
+
+/// Returns whether the uncompressed name at the start of `wire` is `name`, ignoring case.
+fn wire_name_eq(mut wire: &[u8], name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    let labels = name.split('.').filter(|_| !name.is_empty());
+    for label in labels {
+        let len = wire[0] as usize;
+        if len != label.len() || wire.len() < len + 1 {
+            return false;
+        }
+        let equal = wire[1..len+1].iter().zip(label.bytes()).all(|(&a, b)| {
+            a.eq_ignore_ascii_case(&b)
+        });
+        if !equal {
+            return false;
+        }
+        wire = &wire[len+1..];
+    }
+    !wire.is_empty() && wire[0] == 0
+}
+
+/// Returns the length of the uncompressed name at the start of `wire`.
+fn name_len(wire: &[u8]) -> usize {
+    let mut pos = 0;
+    while wire[pos] != 0 {
+        pos += wire[pos] as usize + 1;
+    }
+    pos + 1
+}
+
+/// Returns the length of the uncompressed record at the start of `wire`.
+fn record_len(wire: &[u8]) -> usize {
+    let pos = name_len(wire) + 2 + 2 + 4;
+    let rdlen = (wire[pos] as usize) << 8 | wire[pos + 1] as usize;
+    pos + 2 + rdlen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::net::{Ipv4Addr};
+    use core::time::{Duration};
+    use alloc::string::{ToString};
+    use crate::{Data, Type, Class};
+
+    fn header() -> Packet {
+        let mut p = Packet::query(1);
+        p.is_query = false;
+        p
+    }
+
+    fn a(name: &str, host: u8) -> Record {
+        Record {
+            name:         name.to_string(),
+            class:        Class::In,
+            time_to_live: Duration::from_secs(60),
+            data:         Data::A(Ipv4Addr::new(192, 0, 2, host)),
+        }
+    }
+
+    fn edns() -> Edns {
+        Edns {
+            udp_payload_size: 1232,
+            extended_rcode:   0,
+            version:          0,
+            dnssec_ok:        false,
+            options:          vec!(),
+        }
+    }
+
+    /// Writes a question, an RRset with one record and an RRset with two records to the
+    /// answer section. Returns the length after the first RRset and the complete length.
+    fn write(w: &mut MessageWriter) -> (usize, usize) {
+        w.question(&Question {
+            name:  "example.com".to_string(),
+            ty:    Type::A,
+            class: Class::In,
+        }).unwrap();
+        w.record(Section::Answer, &a("www.example.com", 1)).unwrap();
+        let kept = w.len();
+        w.record(Section::Answer, &a("example.com", 2)).unwrap();
+        w.record(Section::Answer, &a("EXAMPLE.com.", 3)).unwrap();
+        (kept, w.len())
+    }
+
+    fn lengths() -> (usize, usize) {
+        let mut buf = [0; 512];
+        write(&mut MessageWriter::new(&mut buf, &header(), Overflow::Truncate).unwrap())
+    }
+
+    #[test]
+    fn rrset_rollback() {
+        let (kept, full) = lengths();
+        let mut buf = vec!(0; full - 1);
+        let mut w = MessageWriter::new(&mut buf, &header(), Overflow::Truncate).unwrap();
+        write(&mut w);
+        assert!(w.is_truncated());
+        // Later records are dropped even if they would fit.
+        w.record(Section::Additional, &a("a", 4)).unwrap();
+        let len = w.finish();
+        assert_eq!(len, kept);
+        let p = Packet::parse(&buf[..len]).unwrap().1;
+        assert!(p.truncated);
+        assert_eq!(p.answer, [a("www.example.com", 1)]);
+        assert!(p.additional.is_empty());
+    }
+
+    /// Records that do not fit into the additional section do not set the TC bit.
+    #[test]
+    fn additional_overflow() {
+        let (_, full) = lengths();
+        let mut buf = vec!(0; full + 10);
+        let mut w = MessageWriter::new(&mut buf, &header(), Overflow::Truncate).unwrap();
+        write(&mut w);
+        w.record(Section::Additional, &a("ns.example.com", 4)).unwrap();
+        assert!(w.is_truncated());
+        let len = w.finish();
+        assert_eq!(len, full);
+        let p = Packet::parse(&buf[..len]).unwrap().1;
+        assert!(!p.truncated);
+        assert_eq!(p.answer.len(), 3);
+    }
+
+    #[test]
+    fn edns_after_truncation() {
+        let (kept, full) = lengths();
+        let opt = len::opt(&edns());
+        // The OPT record fits after the records that were kept.
+        let mut buf = vec!(0; kept + opt + 1);
+        let mut w = MessageWriter::new(&mut buf, &header(), Overflow::Truncate).unwrap();
+        write(&mut w);
+        w.edns(&edns()).unwrap();
+        let len = w.finish();
+        let p = Packet::parse(&buf[..len]).unwrap().1;
+        assert!(p.truncated);
+        assert_eq!(p.answer.len(), 1);
+        assert_eq!(p.edns, Some(edns()));
+        // The last RRset is removed to make room for the OPT record.
+        let mut buf = vec!(0; full + opt - 1);
+        let mut w = MessageWriter::new(&mut buf, &header(), Overflow::Truncate).unwrap();
+        write(&mut w);
+        assert!(!w.is_truncated());
+        w.edns(&edns()).unwrap();
+        assert!(w.is_truncated());
+        let len = w.finish();
+        assert_eq!(len, kept + opt);
+        let p = Packet::parse(&buf[..len]).unwrap().1;
+        assert!(p.truncated);
+        assert_eq!(p.answer, [a("www.example.com", 1)]);
+        assert_eq!(p.edns, Some(edns()));
+    }
+
+    #[test]
+    fn overflow_error() {
+        let (kept, _) = lengths();
+        let mut buf = vec!(0; kept + 10);
+        let mut w = MessageWriter::new(&mut buf, &header(), Overflow::Error).unwrap();
+        w.question(&Question {
+            name:  "example.com".to_string(),
+            ty:    Type::A,
+            class: Class::In,
+        }).unwrap();
+        w.record(Section::Answer, &a("www.example.com", 1)).unwrap();
+        let record = len::record(&a("example.com", 2));
+        assert_eq!(w.record(Section::Answer, &a("example.com", 2)),
+                   Err(FormatError::Buffer(kept + record)));
+        assert_eq!(w.edns(&edns()), Err(FormatError::Buffer(kept + len::opt(&edns()))));
+        assert!(!w.is_truncated());
+        let len = w.finish();
+        assert_eq!(len, kept);
+        let p = Packet::parse(&buf[..len]).unwrap().1;
+        assert!(!p.truncated);
+        assert_eq!(p.answer, [a("www.example.com", 1)]);
+    }
+
+    #[test]
+    fn limits() {
+        let mut buf = vec!(0; 12 + 5 * 0x10000);
+        let mut w = MessageWriter::new(&mut buf, &header(), Overflow::Error).unwrap();
+        let txt = Record {
+            data: Data::Txt(vec!(vec!(0; 255); 257)),
+            ..a("example.com", 1)
+        };
+        assert_eq!(w.record(Section::Answer, &txt), Err(FormatError::Size));
+        let mut w = MessageWriter::new(&mut buf, &header(), Overflow::Error).unwrap();
+        for _ in 0..0xffff {
+            w.question_raw("", 1, 1).unwrap();
+        }
+        assert_eq!(w.question_raw("", 1, 1), Err(FormatError::Size));
+        assert_eq!(w.finish(), 12 + 5 * 0xffff);
+    }
+}
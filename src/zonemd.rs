@@ -0,0 +1,194 @@
+//! Zone message digests.
+//!
+//! See RFC 8976 for more details.
+
+use alloc::vec::{Vec};
+use alloc::string::{ToString};
+
+use crate::{Data, Record, Type};
+use crate::canonical;
+use crate::sha2::{Sha512};
+
+/// The only defined digest scheme.
+const SIMPLE: u8 = 1;
+
+/// A ZONEMD hash algorithm.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Hash {
+    /// SHA-384.
+    Sha384 = 1,
+    /// SHA-512.
+    Sha512 = 2,
+}
+
+impl Hash {
+    fn from_u8(hash: u8) -> Option<Hash> {
+        match hash {
+            1 => Some(Hash::Sha384),
+            2 => Some(Hash::Sha512),
+            _ => None,
+        }
+    }
+}
+
+/// An error that can occur during verification.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VerifyError {
+    /// The zone has no SOA record at the apex.
+    NoSoa,
+    /// The zone has no ZONEMD record at the apex.
+    NoZonemd,
+    /// No ZONEMD record has the serial of the SOA record.
+    Serial,
+    /// No ZONEMD record uses a supported scheme and hash algorithm.
+    Unsupported,
+    /// A digest does not match the zone contents.
+    Mismatch,
+}
+
+/// Computes the simple scheme digest of a zone.
+///
+/// `records` are the records of the zone with apex `origin` in any order. Records
+/// outside of the zone, ZONEMD records at the apex and duplicates are ignored.
+pub fn digest(origin: &str, records: &[Record], hash: Hash) -> Vec<u8> {
+    let mut ctx = match hash {
+        Hash::Sha384 => Sha512::new384(),
+        Hash::Sha512 => Sha512::new512(),
+    };
+    for r in canonical::sort(records) {
+        if !canonical::is_subdomain(&r.name, origin) {
+            continue;
+        }
+        if r.data.to_type() == Type::Zonemd && canonical::names_equal(&r.name, origin) {
+            continue;
+        }
+        ctx.update(&canonical::record(r));
+    }
+    ctx.finish()
+}
+
+/// Creates the ZONEMD record of a zone.
+///
+/// The serial and TTL are taken from the SOA record at the apex.
+pub fn zonemd(origin: &str, records: &[Record], hash: Hash) -> Result<Record, ()> {
+    let soa = match soa(origin, records) {
+        Some(soa) => soa,
+        _ => return Err(()),
+    };
+    let serial = match soa.data {
+        Data::Soa(_, _, serial, ..) => serial,
+        _ => unreachable!(),
+    };
+    Ok(Record {
+        name:         origin.to_string(),
+        class:        soa.class,
+        time_to_live: soa.time_to_live,
+        data:         Data::Zonemd(serial, SIMPLE, hash as u8, digest(origin, records, hash)),
+    })
+}
+
+/// Verifies the zone's ZONEMD records against its contents.
+///
+/// Succeeds if at least one ZONEMD record with the current serial and a supported
+/// scheme and hash algorithm matches. ZONEMD records with the same scheme and hash
+/// algorithm as another one are never considered.
+pub fn verify(origin: &str, records: &[Record]) -> Result<(), VerifyError> {
+    let serial = match soa(origin, records) {
+        Some(&Record { data: Data::Soa(_, _, serial, ..), .. }) => serial,
+        _ => return Err(VerifyError::NoSoa),
+    };
+    let zonemds: Vec<_> = records.iter().filter_map(|r| match r.data {
+        Data::Zonemd(serial, scheme, hash, ref digest)
+                if canonical::names_equal(&r.name, origin) => {
+            Some((serial, scheme, hash, digest))
+        },
+        _ => None,
+    }).collect();
+    if zonemds.is_empty() {
+        return Err(VerifyError::NoZonemd);
+    }
+    let mut err = VerifyError::Serial;
+    for &(zserial, scheme, hash, digest) in &zonemds {
+        if zserial != serial {
+            continue;
+        }
+        let duplicate = zonemds.iter().filter(|z| z.1 == scheme && z.2 == hash).count() > 1;
+        let hash = match Hash::from_u8(hash) {
+            Some(hash) if scheme == SIMPLE && !duplicate => hash,
+            _ => {
+                if err == VerifyError::Serial {
+                    err = VerifyError::Unsupported;
+                }
+                continue;
+            },
+        };
+        if *digest == self::digest(origin, records, hash) {
+            return Ok(());
+        }
+        err = VerifyError::Mismatch;
+    }
+    Err(err)
+}
+
+fn soa<'a>(origin: &str, records: &'a [Record]) -> Option<&'a Record> {
+    records.iter().find(|r| {
+        r.data.to_type() == Type::Soa && canonical::names_equal(&r.name, origin)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::time::{Duration};
+
+    use alloc::string::{String};
+
+    fn rr(name: &str, ttl: u64, data: Data) -> Record {
+        Record {
+            name:         name.to_string(),
+            class:        crate::Class::In,
+            time_to_live: Duration::from_secs(ttl),
+            data:         data,
+        }
+    }
+
+    fn unhex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap()).collect()
+    }
+
+    /// The simple example zone of RFC 8976 appendix A.1.
+    fn simple_zone() -> Vec<Record> {
+        let digest = unhex("c68090d90a7aed71 6bc459f9340e3d7c 1370d4d24b7e2fc3
+                            a1ddc0b9a87153b9 a9713b3c9ae5cc27 777f98b8e730044c");
+        let secs = Duration::from_secs;
+        vec!(
+            rr("example", 86400, Data::Soa("ns1.example".to_string(),
+                                           "admin.example".to_string(), 2018031900,
+                                           secs(1800), secs(900), secs(604800),
+                                           secs(86400))),
+            rr("example", 86400, Data::Ns("ns1.example".to_string())),
+            rr("example", 86400, Data::Ns("ns2.example".to_string())),
+            rr("example", 86400, Data::Zonemd(2018031900, 1, 1, digest)),
+            rr("ns1.example", 3600, Data::A("203.0.113.63".parse().unwrap())),
+            rr("NS2.EXAMPLE", 3600, Data::Aaaa("2001:db8::63".parse().unwrap())),
+        )
+    }
+
+    #[test]
+    fn rfc8976_simple() {
+        let zone = simple_zone();
+        assert_eq!(verify("example", &zone), Ok(()));
+        assert_eq!(zonemd("example.", &zone, Hash::Sha384).unwrap().data, zone[3].data);
+    }
+
+    #[test]
+    fn mismatch() {
+        let mut zone = simple_zone();
+        zone[4].data = Data::A("203.0.113.64".parse().unwrap());
+        assert_eq!(verify("example", &zone), Err(VerifyError::Mismatch));
+        zone.remove(3);
+        assert_eq!(verify("example", &zone), Err(VerifyError::NoZonemd));
+    }
+}